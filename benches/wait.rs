@@ -0,0 +1,39 @@
+extern crate criterion;
+extern crate nix;
+extern crate signal;
+
+use std::time::{Instant, Duration};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use nix::sys::signal::raise;
+use signal::Signal::{SIGUSR1, SIGUSR2};
+
+use signal::trap::Trap;
+
+
+fn wait_timeout(c: &mut Criterion) {
+    let trap = Trap::trap(&[SIGUSR1]).unwrap();
+    c.bench_function("wait_past_deadline", |b| {
+        b.iter(|| trap.wait(Instant::now()))
+    });
+    c.bench_function("wait_far_deadline_pending", |b| {
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        b.iter(|| {
+            raise(SIGUSR1.into()).unwrap();
+            trap.wait(deadline)
+        })
+    });
+}
+
+fn next_pending(c: &mut Criterion) {
+    let mut trap = Trap::trap(&[SIGUSR2]).unwrap();
+    c.bench_function("next_pending", |b| {
+        b.iter(|| {
+            raise(SIGUSR2.into()).unwrap();
+            trap.next()
+        })
+    });
+}
+
+criterion_group!(benches, wait_timeout, next_pending);
+criterion_main!(benches);
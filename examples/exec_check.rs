@@ -0,0 +1,39 @@
+//! End-to-end check of the `exec_handler`
+//!
+//! Run with `cargo run --example exec_check`. The process installs the
+//! handler executing itself with a marker argument and environment variable,
+//! raises the signal, and the executed process verifies it got the expected
+//! command-line. Exits with non-zero status on failure, so it's suitable to
+//! be run in CI.
+extern crate signal;
+extern crate nix;
+
+use std::env::{args, var, current_exe};
+use std::process::exit;
+
+use nix::sys::signal::raise;
+use signal::Signal::SIGUSR1;
+
+use signal::exec_handler::{CommandLine, Options, set_handler_with};
+
+
+fn main() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() > 1 {
+        let env = var("EXEC_CHECK").unwrap_or_default();
+        if args[1..] == ["executed", "with spaces"] && env == "marker" {
+            println!("Exec handler works");
+            exit(0);
+        }
+        println!("Unexpected command-line {:?}, env {:?}", args, env);
+        exit(1);
+    }
+    let command_line = CommandLine::new(
+        current_exe().unwrap(),
+        ["exec_check", "executed", "with spaces"],
+        vec![("EXEC_CHECK", "marker")]).unwrap();
+    set_handler_with(&[SIGUSR1], command_line, Options::new()).unwrap();
+    raise(SIGUSR1.into()).unwrap();
+    println!("Handler didn't execute the command");
+    exit(1);
+}
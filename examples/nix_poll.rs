@@ -0,0 +1,49 @@
+//! Waits for input on stdin and for signals with `nix::poll::poll`
+//!
+//! Type lines to see them echoed, press Ctrl+C or send `SIGTERM` to stop.
+extern crate signal;
+extern crate nix;
+
+#[cfg(target_os="linux")]
+use std::io::{stdin, BufRead};
+
+#[cfg(target_os="linux")]
+use nix::poll::{poll, PollFd, PollFlags};
+#[cfg(target_os="linux")]
+use signal::Signal::{SIGINT, SIGTERM};
+
+#[cfg(target_os="linux")]
+use signal::trap::Trap;
+
+
+#[cfg(target_os="linux")]
+fn main() {
+    let trap = Trap::trap_via_signalfd(&[SIGINT, SIGTERM]).unwrap();
+    let stdin = stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let mut fds = [
+            PollFd::new(0, PollFlags::POLLIN),
+            trap.poll_fd().unwrap(),
+        ];
+        poll(&mut fds, -1).unwrap();
+        if let Some(sig) = trap.read_polled(&fds[1]) {
+            println!("Stopping because of {}", sig);
+            break;
+        }
+        let stdin_ready = fds[0].revents()
+            .map(|r| !r.is_empty())
+            .unwrap_or(false);
+        if stdin_ready {
+            match lines.next() {
+                Some(line) => println!("Got {:?}", line.unwrap()),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os="linux"))]
+fn main() {
+    println!("unfortunately this example works only for linux");
+}
@@ -1,27 +1,22 @@
 extern crate signal;
 extern crate nix;
 
-use std::time::{Instant, Duration};
+use std::time::Duration;
 use std::thread::sleep;
 
-use nix::sys::signal::{SIGINT};
+use signal::Signal::SIGINT;
 
-use signal::trap::Trap;
+use signal::trap::{Trap, Deadline};
 
 
-#[cfg(target_os="linux")]
 fn main() {
-    let trap = Trap::trap(&[SIGINT]);
+    let trap = Trap::trap(&[SIGINT]).unwrap();
     loop {
-        if let Some(SIGINT) = trap.wait(Instant::now()) {
+        let deadline = Deadline::after(Duration::from_secs(0));
+        if let Some(SIGINT) = trap.wait_deadline(deadline) {
             println!("Gracefully interrupted...");
             break;
         }
         sleep(Duration::from_millis(100));
     }
 }
-
-#[cfg(not(target_os="linux"))]
-fn main() {
-    println!("unfortunately this example works only for linux");
-}
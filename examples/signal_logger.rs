@@ -0,0 +1,32 @@
+//! Logs every signal received until `SIGINT` or `SIGTERM`
+//!
+//! Try `kill -USR1 <pid>` or `kill -WINCH <pid>` from other terminal.
+extern crate signal;
+extern crate nix;
+
+#[cfg(target_os="linux")]
+use signal::Signal::{SIGINT, SIGTERM};
+#[cfg(target_os="linux")]
+use nix::unistd::getpid;
+
+#[cfg(target_os="linux")]
+use signal::logger::SignalLogger;
+
+
+#[cfg(target_os="linux")]
+fn main() {
+    let mut logger = SignalLogger::catch_all().unwrap();
+    println!("Logging signals of pid {}", getpid());
+    loop {
+        let info = logger.log_next().unwrap();
+        match info.signal() {
+            Some(SIGINT) | Some(SIGTERM) => break,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(not(target_os="linux"))]
+fn main() {
+    println!("unfortunately this example works only for linux");
+}
@@ -0,0 +1,216 @@
+//! Awaiting trapped signals on any async runtime
+//!
+//! `AsyncTrap` allows to `await` signals of a trap. It works on any executor
+//! (async-std, smol, tokio, a hand written one...), since it doesn't depend
+//! on a particular reactor. Instead, the `signalfd` of the trap is watched
+//! by a helper thread which only waits until the descriptor is readable and
+//! wakes the task. The helper thread sleeps while the task doesn't wait for
+//! signals, and never reads signals itself.
+//!
+//! Since the readiness is checked by another thread, only signals sent to
+//! the process (e.g. by `kill`) wake the task. Signals directed to the
+//! thread owning the trap (e.g. by `raise` or `pthread_kill`) are only
+//! returned when the trap is polled for some other reason.
+//!
+//! As with `Trap`, the async trap must be dropped in the thread where it was
+//! created, so it should be used on a single-threaded executor (or with
+//! a task that is never moved between threads).
+//!
+//! Only available on linux.
+
+use std::fmt;
+use std::future::Future;
+use std::os::unix::io::RawFd;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+
+use libc;
+use nix;
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
+use Signal;
+use nix::unistd;
+
+use ffi::read_signalfd;
+use from_raw;
+use trap::Trap;
+
+
+/// A trap which signals can be awaited
+///
+/// Created by `Trap::into_async()`.
+pub struct AsyncTrap {
+    trap: Trap,
+    fd: RawFd,
+    shared: Arc<Shared>,
+    /// Write end of the pipe used to stop the helper thread
+    stop: RawFd,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    armed: Condvar,
+}
+
+struct State {
+    waker: Option<Waker>,
+    shutdown: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl AsyncTrap {
+    /// Wrap the trap created by `trap_via_signalfd()`
+    pub(crate) fn new(trap: Trap) -> nix::Result<AsyncTrap> {
+        let fd = trap.signal_fd()
+            .expect("trap is created by trap_via_signalfd()");
+        let (stop_read, stop) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { waker: None, shutdown: false }),
+            armed: Condvar::new(),
+        });
+        let thread_shared = shared.clone();
+        // signals are already blocked in this thread, and the mask is
+        // inherited, so the helper thread can't swallow them
+        let thread = thread::Builder::new()
+            .name("signal-stream".into())
+            .spawn(move || watch(fd, stop_read, &thread_shared));
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(e) => {
+                let _ = unistd::close(stop_read);
+                let _ = unistd::close(stop);
+                return Err(nix::Error::Sys(Errno::from_i32(
+                    e.raw_os_error().unwrap_or(libc::EAGAIN))));
+            }
+        };
+        Ok(AsyncTrap {
+            trap,
+            fd,
+            shared,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the underlying trap
+    pub fn trap(&self) -> &Trap {
+        &self.trap
+    }
+}
+
+/// Body of the helper thread
+///
+/// Waits until the task registers a waker, then until the descriptor is
+/// readable, then wakes the task.
+fn watch(fd: RawFd, stop: RawFd, shared: &Shared) {
+    loop {
+        {
+            let mut state = shared.lock();
+            while state.waker.is_none() && !state.shutdown {
+                state = shared.armed.wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            if state.shutdown {
+                break;
+            }
+        }
+        let mut fds = [
+            PollFd::new(fd, PollFlags::POLLIN),
+            PollFd::new(stop, PollFlags::POLLIN),
+        ];
+        match poll(&mut fds, -1) {
+            Ok(_) => {}
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => panic!("Poll error: {}", e),
+        }
+        if fds[1].revents().map(|r| !r.is_empty()).unwrap_or(false) {
+            break;
+        }
+        let waker = shared.lock().waker.take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+    let _ = unistd::close(stop);
+}
+
+impl AsyncTrap {
+    /// Wait for the next signal
+    ///
+    /// Resolves to `None` if the trap has no signals.
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv { trap: self }
+    }
+
+    /// Poll for the next signal
+    ///
+    /// Returns `Ready(None)` if the trap has no signals. Otherwise, if no
+    /// signal is pending, the task is woken when one arrives.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>)
+        -> Poll<Option<Signal>>
+    {
+        if self.trap.is_empty() {
+            return Poll::Ready(None);
+        }
+        match read_signalfd(self.fd) {
+            Ok(Some(info)) => {
+                if let Some(sig) = from_raw(info.ssi_signo as libc::c_int) {
+                    return Poll::Ready(Some(self.trap.received(sig)));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => panic!("Signalfd read error: {}", e),
+        }
+        // a signal arriving after the read above is noticed by the helper
+        // thread as the descriptor is readable until the signal is read
+        self.shared.lock().waker = Some(cx.waker().clone());
+        self.shared.armed.notify_one();
+        Poll::Pending
+    }
+}
+
+/// Future returned by `AsyncTrap::recv()`
+#[derive(Debug)]
+pub struct Recv<'a> {
+    trap: &'a mut AsyncTrap,
+}
+
+impl<'a> Future for Recv<'a> {
+    type Output = Option<Signal>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>)
+        -> Poll<Option<Signal>>
+    {
+        self.trap.poll_recv(cx)
+    }
+}
+
+impl Drop for AsyncTrap {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.armed.notify_one();
+        let _ = unistd::write(self.stop, b"x");
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = unistd::close(self.stop);
+        // the trap is dropped afterwards, restoring the mask
+    }
+}
+
+impl fmt::Debug for AsyncTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncTrap")
+            .field("trap", &self.trap)
+            .finish()
+    }
+}
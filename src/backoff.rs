@@ -0,0 +1,123 @@
+//! Delays between restarts of a crashing process
+//!
+//! A `Backoff` computes how long to wait before the next restart, so a
+//! process that crashes right after the start doesn't make its supervisor
+//! spin the CPU. The delay is either fixed or grows exponentially up to a
+//! cap, optionally with random jitter so that many processes failing for
+//! the same reason (e.g. a database being down) don't restart in lockstep.
+//!
+//! Used by `Supervisor::backoff()` and `exec_handler::set_restart_backoff()`.
+
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+
+/// A policy of delays between restarts
+///
+/// The first restart is attempt zero. When `reset_after()` is set, a
+/// process that ran for at least that long is considered healthy and the
+/// next restart is attempt zero again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    factor: u32,
+    jitter: f64,
+    reset_after: Option<Duration>,
+}
+
+impl Backoff {
+    /// Restart immediately
+    pub fn none() -> Backoff {
+        Backoff::fixed(Duration::from_secs(0))
+    }
+
+    /// Wait the same time before every restart
+    pub fn fixed(delay: Duration) -> Backoff {
+        Backoff {
+            initial: delay,
+            max: delay,
+            factor: 1,
+            jitter: 0.0,
+            reset_after: None,
+        }
+    }
+
+    /// Start with `initial` and double the delay on every restart, up to
+    /// `max`
+    pub fn exponential(initial: Duration, max: Duration) -> Backoff {
+        Backoff {
+            initial,
+            max: cmp::max(initial, max),
+            factor: 2,
+            jitter: 0.0,
+            reset_after: None,
+        }
+    }
+
+    /// Multiply the delay by `factor` instead of two
+    pub fn factor(mut self, factor: u32) -> Backoff {
+        self.factor = cmp::max(factor, 1);
+        self
+    }
+
+    /// Reduce every delay by a random fraction up to `fraction`
+    ///
+    /// E.g. with `0.5` the delay is between half and the full computed
+    /// value. The fraction is clamped to `0.0..=1.0`, and `1.0` is the
+    /// "full jitter" (anywhere between zero and the computed delay).
+    pub fn jitter(mut self, fraction: f64) -> Backoff {
+        self.jitter = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    /// Start over from the initial delay after the process ran this long
+    pub fn reset_after(mut self, uptime: Duration) -> Backoff {
+        self.reset_after = Some(uptime);
+        self
+    }
+
+    /// Returns the uptime after which the attempts are reset, if any
+    pub fn reset_threshold(&self) -> Option<Duration> {
+        self.reset_after
+    }
+
+    /// Returns the delay before the restart number `attempt`
+    ///
+    /// Jitter is random, so the result may differ between calls.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = self.initial;
+        for _ in 0..attempt {
+            if delay >= self.max {
+                break;
+            }
+            delay = delay.checked_mul(self.factor).unwrap_or(self.max);
+        }
+        let delay = cmp::min(delay, self.max);
+        if self.jitter > 0.0 {
+            delay.mul_f64(1.0 - self.jitter * random_fraction())
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff::none()
+    }
+}
+
+/// A random number in `0.0..1.0`, good enough for jitter
+///
+/// Hasher keys of `RandomState` are random, so this needs no dependencies.
+fn random_fraction() -> f64 {
+    let value = RandomState::new().build_hasher().finish();
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,219 @@
+//! Inspecting children with `waitid`
+//!
+//! `ChildWatcher` reports the state change of a child along with the
+//! resource usage of the child, which `waitpid` doesn't return. With
+//! `peek()` the child is left a zombie (`WNOWAIT`), so the supervisor can
+//! look at the exit status (e.g. to log it or to decide on a restart)
+//! before the pid is released by `reap()`, and the pid can't be reused by
+//! another process in the meantime.
+//!
+//! Only available on linux, as resource usage is returned by the `waitid`
+//! system call itself there.
+
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use libc::{self, c_int, uid_t};
+use nix;
+use nix::errno::Errno;
+use nix::sys::signal::Signal as NixSignal;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+
+/// Resource usage of a child, from `struct rusage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    /// User CPU time used
+    pub user_time: Duration,
+    /// System CPU time used
+    pub system_time: Duration,
+    /// Maximum resident set size, in kilobytes
+    pub max_rss: u64,
+    /// Page faults serviced without I/O
+    pub minor_faults: u64,
+    /// Page faults serviced with I/O
+    pub major_faults: u64,
+    /// Voluntary context switches (e.g. waiting for I/O)
+    pub voluntary_switches: u64,
+    /// Involuntary context switches (preempted by the scheduler)
+    pub involuntary_switches: u64,
+}
+
+/// The state change of a child reported by `ChildWatcher`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildInfo {
+    /// Pid of the child
+    pub pid: Pid,
+    /// Real user id of the child
+    pub uid: uid_t,
+    /// Exited, killed, stopped or continued
+    pub status: WaitStatus,
+    /// Resource usage of the child (and of its reaped descendants)
+    pub rusage: ResourceUsage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Any,
+    Pid(Pid),
+    Group(Pid),
+    PidFd(RawFd),
+}
+
+/// Not in `libc` yet, linux 5.4 and later
+const P_PIDFD: libc::idtype_t = 3;
+
+/// Waits for children with `waitid`
+///
+/// By default only exited children are reported, use `stopped()` and
+/// `continued()` to watch for the job control state changes too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildWatcher {
+    target: Target,
+    flags: c_int,
+}
+
+impl ChildWatcher {
+    /// Watch any child of the process
+    pub fn any() -> ChildWatcher {
+        ChildWatcher::new(Target::Any)
+    }
+
+    /// Watch a specific child
+    pub fn pid(pid: Pid) -> ChildWatcher {
+        ChildWatcher::new(Target::Pid(pid))
+    }
+
+    /// Watch the children in the process group
+    pub fn group(pgid: Pid) -> ChildWatcher {
+        ChildWatcher::new(Target::Group(pgid))
+    }
+
+    /// Watch the child referred to by the descriptor, see `pidfd::PidFd`
+    pub(crate) fn pidfd(fd: RawFd) -> ChildWatcher {
+        ChildWatcher::new(Target::PidFd(fd))
+    }
+
+    fn new(target: Target) -> ChildWatcher {
+        ChildWatcher { target, flags: libc::WEXITED }
+    }
+
+    /// Also report children stopped by a signal (`WSTOPPED`)
+    pub fn stopped(mut self, value: bool) -> ChildWatcher {
+        self.set_flag(libc::WSTOPPED, value);
+        self
+    }
+
+    /// Also report stopped children resumed by `SIGCONT` (`WCONTINUED`)
+    pub fn continued(mut self, value: bool) -> ChildWatcher {
+        self.set_flag(libc::WCONTINUED, value);
+        self
+    }
+
+    fn set_flag(&mut self, flag: c_int, value: bool) {
+        if value {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    /// Returns the state change of a child without reaping it
+    ///
+    /// The same child is returned again by the next call, until it's reaped
+    /// by `reap()` or `wait()` (for the exited child) or until the state
+    /// changes again. Returns `None` if no watched child changed its state,
+    /// and `ECHILD` if there are no such children at all.
+    pub fn peek(&self) -> nix::Result<Option<ChildInfo>> {
+        self.waitid(libc::WNOHANG | libc::WNOWAIT)
+    }
+
+    /// Returns the state change of a child, reaping it if it has exited
+    ///
+    /// Returns `None` if no watched child changed its state, never blocks.
+    pub fn reap(&self) -> nix::Result<Option<ChildInfo>> {
+        self.waitid(libc::WNOHANG)
+    }
+
+    /// Block until a watched child changes its state, reaping it if exited
+    pub fn wait(&self) -> nix::Result<ChildInfo> {
+        loop {
+            match self.waitid(0) {
+                Ok(Some(info)) => return Ok(info),
+                // spurious wakeup, not expected without `WNOHANG`
+                Ok(None) => continue,
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn waitid(&self, flags: c_int) -> nix::Result<Option<ChildInfo>> {
+        let (idtype, id) = match self.target {
+            Target::Any => (libc::P_ALL, 0),
+            Target::Pid(pid) => (libc::P_PID, pid.as_raw() as libc::id_t),
+            Target::Group(pgid) => {
+                (libc::P_PGID, pgid.as_raw() as libc::id_t)
+            }
+            Target::PidFd(fd) => (P_PIDFD, fd as libc::id_t),
+        };
+        unsafe {
+            // `si_pid` is left zero if there is nothing to report
+            let mut info: libc::siginfo_t = mem::zeroed();
+            let mut rusage: libc::rusage = mem::zeroed();
+            // the system call has the fifth argument libc doesn't expose
+            let res = libc::syscall(libc::SYS_waitid, idtype, id,
+                &mut info as *mut libc::siginfo_t, self.flags | flags,
+                &mut rusage as *mut libc::rusage);
+            if res < 0 {
+                return Err(nix::Error::last());
+            }
+            if info.si_pid() == 0 {
+                return Ok(None);
+            }
+            Ok(Some(ChildInfo {
+                pid: Pid::from_raw(info.si_pid()),
+                uid: info.si_uid(),
+                status: wait_status(&info)?,
+                rusage: ResourceUsage::from(&rusage),
+            }))
+        }
+    }
+}
+
+unsafe fn wait_status(info: &libc::siginfo_t) -> nix::Result<WaitStatus> {
+    let pid = Pid::from_raw(info.si_pid());
+    let status = info.si_status();
+    let signal = || NixSignal::from_c_int(status);
+    let result = match info.si_code {
+        libc::CLD_EXITED => WaitStatus::Exited(pid, status),
+        libc::CLD_KILLED => WaitStatus::Signaled(pid, signal()?, false),
+        libc::CLD_DUMPED => WaitStatus::Signaled(pid, signal()?, true),
+        libc::CLD_STOPPED | libc::CLD_TRAPPED => {
+            WaitStatus::Stopped(pid, signal()?)
+        }
+        libc::CLD_CONTINUED => WaitStatus::Continued(pid),
+        _ => return Err(nix::Error::Sys(Errno::EINVAL)),
+    };
+    Ok(result)
+}
+
+impl<'a> From<&'a libc::rusage> for ResourceUsage {
+    fn from(usage: &'a libc::rusage) -> ResourceUsage {
+        ResourceUsage {
+            user_time: duration(&usage.ru_utime),
+            system_time: duration(&usage.ru_stime),
+            max_rss: usage.ru_maxrss as u64,
+            minor_faults: usage.ru_minflt as u64,
+            major_faults: usage.ru_majflt as u64,
+            voluntary_switches: usage.ru_nvcsw as u64,
+            involuntary_switches: usage.ru_nivcsw as u64,
+        }
+    }
+}
+
+fn duration(tv: &libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
@@ -0,0 +1,168 @@
+//! Reaping of specific child processes
+//!
+//! The usual way to handle `SIGCHLD` is to call `waitpid(-1, WNOHANG)` in a
+//! loop until there are no more children to reap. But that steals exit
+//! status of children started by other parts of the program (e.g. by
+//! `std::process::Command::output()` running in some other thread).
+//!
+//! `Children` keeps track of pids registered by `watch_child()` and reaps
+//! only those. Use it together with a `Trap` for `SIGCHLD`: every time
+//! `SIGCHLD` is received call `reap()`. Note that multiple child deaths may
+//! be coalesced into a single `SIGCHLD`, `reap()` checks every watched child
+//! so that doesn't matter.
+//!
+//! When the process owns all of its children, `reap_any()` does the usual
+//! loop instead, and `TrapBuilder::reap_children()` makes a trap do it on
+//! every `SIGCHLD`.
+
+use std::collections::HashSet;
+
+use nix;
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+
+
+/// A child process that exited and was reaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildEvent {
+    /// Pid of the child
+    pub pid: Pid,
+    /// Either `WaitStatus::Exited` or `WaitStatus::Signaled`
+    pub status: WaitStatus,
+}
+
+/// A set of child processes that are reaped on `SIGCHLD`
+#[derive(Debug, Default)]
+pub struct Children {
+    pids: HashSet<Pid>,
+}
+
+impl Children {
+    /// Create an empty set of children
+    pub fn new() -> Children {
+        Children {
+            pids: HashSet::new(),
+        }
+    }
+
+    /// Start watching a child process
+    pub fn watch_child(&mut self, pid: Pid) {
+        self.pids.insert(pid);
+    }
+
+    /// Stop watching a child process
+    ///
+    /// Returns `false` if the process wasn't watched
+    pub fn unwatch_child(&mut self, pid: Pid) -> bool {
+        self.pids.remove(&pid)
+    }
+
+    /// Returns `true` if the child process is watched (i.e. it wasn't
+    /// reaped yet)
+    pub fn is_watched(&self, pid: Pid) -> bool {
+        self.pids.contains(&pid)
+    }
+
+    /// Returns number of children that are not reaped yet
+    pub fn len(&self) -> usize {
+        self.pids.len()
+    }
+
+    /// Returns `true` if there are no more children to wait for
+    pub fn is_empty(&self) -> bool {
+        self.pids.is_empty()
+    }
+
+    /// Reap all watched children that have exited
+    ///
+    /// Returns statuses of the exited children (which are either
+    /// `WaitStatus::Exited` or `WaitStatus::Signaled`), the children are
+    /// removed from the set.
+    ///
+    /// If the child was already reaped by someone else (`waitpid` returns
+    /// `ECHILD`) it's silently removed from the set.
+    pub fn reap(&mut self) -> nix::Result<Vec<WaitStatus>> {
+        let mut result = Vec::new();
+        let mut gone = Vec::new();
+        let mut error = None;
+        'children: for &pid in &self.pids {
+            loop {
+                match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(WaitStatus::StillAlive) => {}
+                    Ok(status @ WaitStatus::Exited(..)) |
+                    Ok(status @ WaitStatus::Signaled(..)) => {
+                        result.push(status);
+                        gone.push(pid);
+                    }
+                    // Only reported with flags we don't pass
+                    Ok(_) => continue,
+                    Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                    Err(nix::Error::Sys(Errno::ECHILD)) => {
+                        gone.push(pid);
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        break 'children;
+                    }
+                }
+                break;
+            }
+        }
+        for pid in gone {
+            self.pids.remove(&pid);
+        }
+        match error {
+            // statuses reaped so far are lost, but it's not expected to
+            // happen in practice
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
+    }
+}
+
+/// Reap all the children that have exited
+///
+/// This is the `waitpid(-1, WNOHANG)` loop, it runs until there are no more
+/// exited children (or no children at all).
+pub fn reap_any() -> Vec<ChildEvent> {
+    let mut result = Vec::new();
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => break,
+            Ok(status @ WaitStatus::Exited(pid, _)) |
+            Ok(status @ WaitStatus::Signaled(pid, ..)) => {
+                result.push(ChildEvent { pid, status });
+            }
+            // Only reported with flags we don't pass
+            Ok(_) => continue,
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            // `ECHILD`, no children left
+            Err(_) => break,
+        }
+    }
+    result
+}
+
+/// Returns a human-readable description of the child status
+///
+/// E.g. `exited with code 1` or `killed by SIGSEGV (core dumped)`. The pid
+/// is not included, so the caller can format it in whatever way it prefers.
+pub fn describe_wait_status(status: WaitStatus) -> String {
+    match status {
+        WaitStatus::Exited(_, code) => format!("exited with code {}", code),
+        WaitStatus::Signaled(_, sig, true) => {
+            format!("killed by {} (core dumped)", sig)
+        }
+        WaitStatus::Signaled(_, sig, false) => format!("killed by {}", sig),
+        WaitStatus::Stopped(_, sig) => format!("stopped by {}", sig),
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        WaitStatus::PtraceEvent(_, sig, event) => {
+            format!("stopped by {} (ptrace event {})", sig, event)
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        WaitStatus::PtraceSyscall(_) => "stopped at syscall".to_string(),
+        WaitStatus::Continued(_) => "continued".to_string(),
+        WaitStatus::StillAlive => "still alive".to_string(),
+    }
+}
@@ -0,0 +1,230 @@
+//! Console control events on Windows
+//!
+//! Windows has no signals, instead console programs receive control events
+//! (when the user presses Ctrl+C, closes the console window...). The
+//! `ConsoleTrap` catches the events and allows to wait for them the same
+//! way `Trap` does for signals, so shutdown code can be shared between
+//! platforms by mapping e.g. `SIGINT` and `CtrlEvent::CtrlC` to the same
+//! application event.
+//!
+//! The events are delivered by the system to a handler running in a new
+//! thread. The handler records the event for the traps and returns
+//! immediately for `CtrlC` and `CtrlBreak`. For the other events the
+//! process is terminated as soon as the handler returns, so the handler
+//! blocks until all the traps catching that event are dropped: drop the
+//! trap when the cleanup is done. Note that the system still terminates
+//! the process after a timeout (5 seconds for closing the console).
+//!
+//! Only available on Windows.
+
+use std::io;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use windows_sys::core::BOOL;
+use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+use windows_sys::Win32::System::Console::{CTRL_C_EVENT, CTRL_BREAK_EVENT};
+use windows_sys::Win32::System::Console::{CTRL_CLOSE_EVENT, CTRL_LOGOFF_EVENT};
+use windows_sys::Win32::System::Console::CTRL_SHUTDOWN_EVENT;
+
+
+/// A console control event, the Windows counterpart of a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CtrlEvent {
+    /// Ctrl+C is pressed (`CTRL_C_EVENT`), like `SIGINT`
+    CtrlC,
+    /// Ctrl+Break is pressed (`CTRL_BREAK_EVENT`), like `SIGQUIT`
+    CtrlBreak,
+    /// The console is closed (`CTRL_CLOSE_EVENT`), like `SIGHUP`
+    Close,
+    /// The user logs off (`CTRL_LOGOFF_EVENT`), only sent to services
+    Logoff,
+    /// The system shuts down (`CTRL_SHUTDOWN_EVENT`), like `SIGTERM`, only
+    /// sent to services
+    Shutdown,
+}
+
+const EVENTS: [CtrlEvent; 5] = [
+    CtrlEvent::CtrlC,
+    CtrlEvent::CtrlBreak,
+    CtrlEvent::Close,
+    CtrlEvent::Logoff,
+    CtrlEvent::Shutdown,
+];
+
+impl CtrlEvent {
+    /// Convert the control type passed to the handler into the event
+    pub fn from_raw(raw: u32) -> Option<CtrlEvent> {
+        match raw {
+            CTRL_C_EVENT => Some(CtrlEvent::CtrlC),
+            CTRL_BREAK_EVENT => Some(CtrlEvent::CtrlBreak),
+            CTRL_CLOSE_EVENT => Some(CtrlEvent::Close),
+            CTRL_LOGOFF_EVENT => Some(CtrlEvent::Logoff),
+            CTRL_SHUTDOWN_EVENT => Some(CtrlEvent::Shutdown),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the process is terminated after the event
+    ///
+    /// This is the case for all events except `CtrlC` and `CtrlBreak`.
+    pub fn is_terminating(&self) -> bool {
+        !matches!(*self, CtrlEvent::CtrlC | CtrlEvent::CtrlBreak)
+    }
+
+    fn index(self) -> usize {
+        EVENTS.iter().position(|&e| e == self).unwrap()
+    }
+}
+
+struct State {
+    /// Number of traps catching the event
+    traps: [usize; 5],
+    /// Events received and not yet consumed
+    pending: [bool; 5],
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    traps: [0; 5],
+    pending: [false; 5],
+});
+static CHANGED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+unsafe extern "system" fn handler(ctrl_type: u32) -> BOOL {
+    let event = match CtrlEvent::from_raw(ctrl_type) {
+        Some(event) => event,
+        None => return 0,
+    };
+    let idx = event.index();
+    let mut state = lock();
+    if state.traps[idx] == 0 {
+        // let the default handler terminate the process
+        return 0;
+    }
+    state.pending[idx] = true;
+    CHANGED.notify_all();
+    if event.is_terminating() {
+        while state.traps[idx] > 0 {
+            state = CHANGED.wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+    1
+}
+
+/// A RAII guard catching console control events
+///
+/// While the trap exists, the events are recorded instead of terminating
+/// the process and can be waited for. Overlapping traps share the handler,
+/// it's removed when the last of them is dropped. An event is consumed by
+/// the first trap that waits for it.
+#[derive(Debug)]
+pub struct ConsoleTrap {
+    events: Vec<CtrlEvent>,
+}
+
+impl ConsoleTrap {
+    /// Start catching the events
+    ///
+    /// The handler isn't registered for an empty list of events.
+    pub fn trap(events: &[CtrlEvent]) -> io::Result<ConsoleTrap> {
+        let mut trapped = Vec::new();
+        for &event in events {
+            if !trapped.contains(&event) {
+                trapped.push(event);
+            }
+        }
+        if trapped.is_empty() {
+            return Ok(ConsoleTrap { events: trapped });
+        }
+        let mut state = lock();
+        if state.traps.iter().all(|&n| n == 0) &&
+            unsafe { SetConsoleCtrlHandler(Some(handler), 1) } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        for &event in &trapped {
+            state.traps[event.index()] += 1;
+        }
+        Ok(ConsoleTrap { events: trapped })
+    }
+
+    /// Returns the events caught by the trap
+    pub fn events(&self) -> &[CtrlEvent] {
+        &self.events
+    }
+
+    /// Wait until any of the events is received or deadline is reached
+    ///
+    /// Returns `None` on deadline. Like `Trap::wait()`, the argument is a
+    /// deadline rather than a timeout.
+    pub fn wait(&self, deadline: Instant) -> Option<CtrlEvent> {
+        let mut state = lock();
+        loop {
+            if let Some(event) = self.take_pending(&mut state) {
+                return Some(event);
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.as_nanos() == 0 {
+                return None;
+            }
+            state = CHANGED.wait_timeout(state, timeout)
+                .unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+
+    /// Returns a received event without blocking
+    pub fn try_wait(&self) -> Option<CtrlEvent> {
+        self.take_pending(&mut lock())
+    }
+
+    fn take_pending(&self, state: &mut State) -> Option<CtrlEvent> {
+        let event = *self.events.iter()
+            .find(|&&event| state.pending[event.index()])?;
+        state.pending[event.index()] = false;
+        Some(event)
+    }
+}
+
+impl Iterator for ConsoleTrap {
+    type Item = CtrlEvent;
+    fn next(&mut self) -> Option<CtrlEvent> {
+        if self.events.is_empty() {
+            return None;
+        }
+        let mut state = lock();
+        loop {
+            if let Some(event) = self.take_pending(&mut state) {
+                return Some(event);
+            }
+            state = CHANGED.wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl Drop for ConsoleTrap {
+    fn drop(&mut self) {
+        if self.events.is_empty() {
+            // the handler wasn't registered by this trap
+            return;
+        }
+        let mut state = lock();
+        for &event in &self.events {
+            let idx = event.index();
+            state.traps[idx] -= 1;
+            if state.traps[idx] == 0 {
+                state.pending[idx] = false;
+            }
+        }
+        if state.traps.iter().all(|&n| n == 0) {
+            unsafe { SetConsoleCtrlHandler(Some(handler), 0) };
+        }
+        // wakes up the handler blocked for a terminating event
+        CHANGED.notify_all();
+    }
+}
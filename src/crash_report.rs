@@ -0,0 +1,159 @@
+//! Writing crash diagnostics from a fatal signal handler
+//!
+//! `write_report()` formats a single line and writes it to the specified
+//! file descriptor (usually stderr or a log file opened in advance), e.g.:
+//! `fatal signal 11 (SIGSEGV) at address 0x0 ip 0x55d0c0de1234 sp 0x7ffd...`
+//!
+//! The crash handler of the `exec_handler` module writes it before
+//! re-executing the process when enabled with
+//! `exec_handler::set_crash_report()`. Custom `SA_SIGINFO` handlers may
+//! call `write_report()` directly.
+//!
+//! The fault address is known for all fatal signals except `SIGABRT`.
+//! Instruction and stack pointers are read from the `ucontext_t` passed to a
+//! `SA_SIGINFO` handler, this is only implemented for linux (glibc) on
+//! `x86_64` and `aarch64` and for macOS, on other platforms they are
+//! omitted.
+//!
+//! Everything is written with a single `write()` from a buffer on the stack,
+//! so the report is async-signal-safe.
+
+use std::os::unix::io::RawFd;
+
+use libc::{self, c_int, c_void, siginfo_t};
+
+use from_raw;
+
+
+/// Write the crash diagnostics line to the file descriptor
+///
+/// This is what the handler set by `exec_handler::set_crash_handler()`
+/// does when `exec_handler::set_crash_report()` is set. It may be called
+/// from a custom `SA_SIGINFO` handler.
+///
+/// # Safety
+///
+/// `info` and `context` must be either null or the pointers received by
+/// the `SA_SIGINFO` signal handler for the signal `sig`.
+pub unsafe fn write_report(fd: RawFd, sig: c_int,
+    info: *const siginfo_t, context: *const c_void)
+{
+    let mut buf = Buffer::new();
+    buf.push(b"fatal signal ");
+    buf.push_decimal(sig as u64);
+    if let Some(signal) = from_raw(sig) {
+        buf.push(b" (");
+        buf.push(signal.as_ref().as_bytes());
+        buf.push(b")");
+    }
+    if !info.is_null() && sig != libc::SIGABRT {
+        buf.push(b" at address 0x");
+        buf.push_hex((*info).si_addr() as usize as u64);
+    }
+    if let Some((ip, sp)) = registers(context) {
+        buf.push(b" ip 0x");
+        buf.push_hex(ip);
+        buf.push(b" sp 0x");
+        buf.push_hex(sp);
+    }
+    buf.push(b"\n");
+    libc::write(fd, buf.data.as_ptr() as *const c_void, buf.len);
+}
+
+/// Returns instruction and stack pointers from the signal context
+#[cfg(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"))]
+unsafe fn registers(context: *const c_void) -> Option<(u64, u64)> {
+    if context.is_null() {
+        return None;
+    }
+    let ctx = &*(context as *const libc::ucontext_t);
+    let regs = &ctx.uc_mcontext.gregs;
+    Some((regs[libc::REG_RIP as usize] as u64,
+          regs[libc::REG_RSP as usize] as u64))
+}
+
+/// Returns instruction and stack pointers from the signal context
+#[cfg(all(target_os = "linux", target_env = "gnu", target_arch = "aarch64"))]
+unsafe fn registers(context: *const c_void) -> Option<(u64, u64)> {
+    if context.is_null() {
+        return None;
+    }
+    let ctx = &*(context as *const libc::ucontext_t);
+    Some((ctx.uc_mcontext.pc as u64, ctx.uc_mcontext.sp as u64))
+}
+
+/// Returns instruction and stack pointers from the signal context
+#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+unsafe fn registers(context: *const c_void) -> Option<(u64, u64)> {
+    if context.is_null() {
+        return None;
+    }
+    let ctx = &*(context as *const libc::ucontext_t);
+    let state = &(*ctx.uc_mcontext).__ss;
+    Some((state.__rip, state.__rsp))
+}
+
+/// Returns instruction and stack pointers from the signal context
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+unsafe fn registers(context: *const c_void) -> Option<(u64, u64)> {
+    if context.is_null() {
+        return None;
+    }
+    let ctx = &*(context as *const libc::ucontext_t);
+    let state = &(*ctx.uc_mcontext).__ss;
+    Some((state.__pc, state.__sp))
+}
+
+/// Registers aren't supported on this platform
+#[cfg(not(any(
+    all(target_os = "linux", target_env = "gnu",
+        any(target_arch = "x86_64", target_arch = "aarch64")),
+    all(target_os = "macos",
+        any(target_arch = "x86_64", target_arch = "aarch64")),
+)))]
+unsafe fn registers(_context: *const c_void) -> Option<(u64, u64)> {
+    None
+}
+
+/// Fixed size buffer to format the report without allocating
+struct Buffer {
+    data: [u8; 256],
+    len: usize,
+}
+
+impl Buffer {
+    fn new() -> Buffer {
+        Buffer { data: [0; 256], len: 0 }
+    }
+    fn push(&mut self, bytes: &[u8]) {
+        let n = bytes.len().min(self.data.len() - self.len);
+        self.data[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+    }
+    fn push_decimal(&mut self, mut value: u64) {
+        let mut digits = [0u8; 20];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (value % 10) as u8;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        self.push(&digits[i..]);
+    }
+    fn push_hex(&mut self, mut value: u64) {
+        let mut digits = [0u8; 16];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b"0123456789abcdef"[(value & 0xf) as usize];
+            value >>= 4;
+            if value == 0 {
+                break;
+            }
+        }
+        self.push(&digits[i..]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Error type of the library
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use libc::c_int;
+use nix;
+use nix::errno::Errno;
+use Signal;
+
+use {from_raw, to_raw};
+
+
+/// Error returned by the functions changing or waiting for signals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The signal can't be caught, blocked or ignored (`SIGKILL`, `SIGSTOP`)
+    Uncatchable(Signal),
+    /// The number doesn't correspond to any signal known to the library
+    InvalidSignal(c_int),
+    /// Setting the signal handler with `sigaction` failed
+    ///
+    /// The signal is a raw number, because it may be a realtime signal.
+    Sigaction(c_int, Errno),
+    /// Changing or reading the signal mask failed
+    Sigmask(Errno),
+    /// Waiting for a signal failed
+    ///
+    /// This includes waiting on a trap without signals (`EINVAL`).
+    Wait(Errno),
+    /// Creating or changing the mask of a `signalfd` failed
+    Signalfd(Errno),
+    /// Setting up the alternate signal stack with `sigaltstack` failed
+    AltStack(Errno),
+}
+
+impl Error {
+    /// Returns the closest `errno` value for the error
+    ///
+    /// Invalid and uncatchable signals are reported as `EINVAL`, like the
+    /// system calls do.
+    pub fn errno(&self) -> Errno {
+        match *self {
+            Error::Uncatchable(_) | Error::InvalidSignal(_) => Errno::EINVAL,
+            Error::Sigaction(_, errno) => errno,
+            Error::Sigmask(errno) => errno,
+            Error::Wait(errno) => errno,
+            Error::Signalfd(errno) => errno,
+            Error::AltStack(errno) => errno,
+        }
+    }
+
+    pub(crate) fn sigaction(sig: Signal, e: nix::Error) -> Error {
+        Error::Sigaction(to_raw(sig), errno_of(e))
+    }
+
+    pub(crate) fn sigmask(e: nix::Error) -> Error {
+        Error::Sigmask(errno_of(e))
+    }
+
+    pub(crate) fn wait(e: nix::Error) -> Error {
+        Error::Wait(errno_of(e))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn signalfd(e: nix::Error) -> Error {
+        Error::Signalfd(errno_of(e))
+    }
+
+    pub(crate) fn alt_stack(e: nix::Error) -> Error {
+        Error::AltStack(errno_of(e))
+    }
+}
+
+/// Non-`Sys` errors aren't returned by the calls used in this library
+fn errno_of(e: nix::Error) -> Errno {
+    e.as_errno().unwrap_or(Errno::EINVAL)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Uncatchable(sig) => {
+                write!(f, "signal {} can't be caught", sig)
+            }
+            Error::InvalidSignal(raw) => write!(f, "invalid signal {}", raw),
+            Error::Sigaction(raw, errno) => {
+                match from_raw(raw) {
+                    Some(sig) => write!(f, "can't set handler for {}", sig)?,
+                    None => write!(f, "can't set handler for signal {}", raw)?,
+                }
+                write!(f, ": {}", errno.desc())
+            }
+            Error::Sigmask(errno) => {
+                write!(f, "can't change signal mask: {}", errno.desc())
+            }
+            Error::Wait(errno) => {
+                write!(f, "error waiting for signal: {}", errno.desc())
+            }
+            Error::Signalfd(errno) => {
+                write!(f, "can't set up signalfd: {}", errno.desc())
+            }
+            Error::AltStack(errno) => {
+                write!(f, "can't set up alternate signal stack: {}",
+                       errno.desc())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        io::Error::new(io::Error::from_raw_os_error(e.errno() as i32).kind(),
+                       e)
+    }
+}
+
+impl From<Error> for nix::Error {
+    fn from(e: Error) -> nix::Error {
+        nix::Error::Sys(e.errno())
+    }
+}
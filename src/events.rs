@@ -0,0 +1,92 @@
+//! Semantic events of a process supervisor
+//!
+//! `Trap::events()` turns the trapped signals into `Event` values, so the
+//! main loop of a supervisor is a single `match` instead of a match on
+//! signals plus the bookkeeping for each of them. Signals are classified by
+//! `groups::classify`: termination requests become `Event::Shutdown`,
+//! `SIGHUP` becomes `Event::Reload` and so on.
+//!
+//! On `SIGCHLD` all the exited children are reaped with
+//! `children::reap_any()`, and an `Event::ChildExited` is yielded for each
+//! of them (a single `SIGCHLD` may stand for several children). This also
+//! reaps children started by other parts of the program, use
+//! `children::Children` if that's a problem.
+
+use std::collections::VecDeque;
+
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+use children::{reap_any, ChildEvent};
+use groups::{classify, SignalClass};
+use trap::Trap;
+use Signal;
+
+
+/// An event of a process supervisor, see the module docs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Termination is requested (`SIGTERM`, `SIGINT` or `SIGQUIT`)
+    Shutdown(Signal),
+    /// Configuration reload is requested (`SIGHUP`)
+    Reload,
+    /// A child process exited and was reaped
+    ChildExited {
+        /// Pid of the child
+        pid: Pid,
+        /// Either `WaitStatus::Exited` or `WaitStatus::Signaled`
+        status: WaitStatus,
+    },
+    /// The terminal window size changed (`SIGWINCH`)
+    WindowResized,
+    /// Any other trapped signal (e.g. `SIGUSR1`)
+    Other(Signal),
+}
+
+/// An iterator adaptor returned by `Trap::events`
+#[derive(Debug)]
+pub struct Events {
+    trap: Trap,
+    queue: VecDeque<Event>,
+}
+
+impl Events {
+    pub(crate) fn new(trap: Trap) -> Events {
+        Events {
+            trap,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the underlying trap
+    pub fn trap(&self) -> &Trap {
+        &self.trap
+    }
+}
+
+impl Iterator for Events {
+    type Item = Event;
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(event);
+            }
+            let sig = self.trap.next()?;
+            match classify(sig) {
+                SignalClass::Terminate => return Some(Event::Shutdown(sig)),
+                SignalClass::Reload => return Some(Event::Reload),
+                // children may be reaped already, then the signal is skipped
+                SignalClass::ChildStatus => {
+                    self.queue.extend(reap_any().into_iter()
+                        .map(|ChildEvent { pid, status }| {
+                            Event::ChildExited { pid, status }
+                        }));
+                }
+                _ if sig == Signal::SIGWINCH => {
+                    return Some(Event::WindowResized);
+                }
+                _ => return Some(Event::Other(sig)),
+            }
+        }
+    }
+}
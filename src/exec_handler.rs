@@ -7,22 +7,88 @@
 //! able to re-execute in place and continue to work. Also may be used for
 //! configuration reloading signal (e.g. SIGHUP) if appropriate.
 
-use std::io::Write;
-use std::mem::{forget, transmute};
-use std::ptr::{null};
-use std::ffi::CString;
-use std::env::{current_exe, args_os, vars_os};
+use std::fmt;
+use std::mem::zeroed;
+use std::ptr::{self, null};
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64};
+use std::sync::atomic::Ordering;
+use std::ffi::{CString, NulError};
+use std::env::{self, current_exe, args_os, vars_os};
+use std::os::unix::io::RawFd;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use nix;
-use libc::{execve, c_char, pid_t, getpid, c_int};
-use nix::sys::signal::{sigaction, SigAction, Signal, SigSet, SaFlags};
+use libc;
+use nix::errno::Errno;
+use libc::{execve, c_char, pid_t, getpid, c_int, c_void, siginfo_t};
+use nix::sys::signal::{sigaction, SigAction, SigSet, SaFlags};
 use nix::sys::signal::{pthread_sigmask, SigmaskHow, SigHandler};
 
-use ffi::{ToCString};
+use backoff::Backoff;
+use crash_report::write_report;
+use ffi::{ToCString, set_alt_stack};
+use groups::FATAL_SIGNALS;
+use last_signal;
+use {to_raw, check_catchable, Error, Signal};
 
 
-static mut EXEC_COMMAND_LINE: *const ExecCommandLine =
-    0usize as *const ExecCommandLine;
+static mut EXEC_COMMAND_LINE: *const ExecCommandLine = null();
+static mut PIDFILE: *const Pidfile = null();
+static mut PRE_EXEC_HOOK: Option<fn()> = None;
+static RESTART_DELAY: RestartDelay = RestartDelay {
+    enabled: AtomicBool::new(false),
+    delay: AtomicU64::new(0),
+    first_delay: AtomicU64::new(0),
+    reset_after: AtomicU64::new(NO_RESET),
+    started: AtomicU64::new(0),
+    next_attempt: AtomicU32::new(0),
+};
+static HANDLER_ENTERED: AtomicBool = AtomicBool::new(false);
+static CRASH_REPORT_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// The `execve` called by the handler, replaced by a mock in tests
+#[cfg(test)]
+static mut EXEC: unsafe extern "C" fn(*const c_char, *const *const c_char,
+    *const *const c_char) -> c_int = execve;
+
+struct Pidfile {
+    fd: RawFd,
+    contents: Vec<u8>,
+}
+
+/// Set by `set_restart_backoff`, all durations are in nanoseconds
+struct RestartDelay {
+    enabled: AtomicBool,
+    delay: AtomicU64,
+    /// Used instead of `delay` if the process ran for `reset_after`
+    first_delay: AtomicU64,
+    /// `NO_RESET` if the attempts are never reset
+    reset_after: AtomicU64,
+    started: AtomicU64,
+    /// Passed to the new process in `RESTART_ATTEMPT_VAR`
+    next_attempt: AtomicU32,
+}
+
+const NO_RESET: u64 = u64::MAX;
+
+/// Environment variable passing the restart attempt to the new process
+///
+/// The value is `ATTEMPT:STARTED`, where `STARTED` is the `CLOCK_MONOTONIC`
+/// time in nanoseconds when the previous process called
+/// `set_restart_backoff`. See `set_restart_backoff` for details.
+pub const RESTART_ATTEMPT_VAR: &str = "SIGNAL_RESTART_ATTEMPT";
+
+/// Environment variable passing the signal to the executed process
+///
+/// The value is `SIGNAL:SECONDS.NANOSECONDS`, the signal number and the
+/// `CLOCK_REALTIME` time when the handler was entered. See
+/// `previous_signal`.
+pub const LAST_SIGNAL_VAR: &str = "SIGNAL_LAST_SIGNAL";
+
+/// Room for the entries filled in by the handler (e.g. `LAST_SIGNAL_VAR=`
+/// and the longest value), including the NUL
+const ENTRY_SIZE: usize = 64;
 
 #[allow(unused)]
 struct ExecCommandLine {
@@ -30,10 +96,142 @@ struct ExecCommandLine {
     args: Vec<CString>,
     c_args: Vec<*const c_char>,
     env: Vec<CString>,
+    /// The `LAST_SIGNAL_VAR` entry of `c_env`, filled in by the handler
+    last_signal: *mut u8,
+    /// The `RESTART_ATTEMPT_VAR` entry, the last one of `c_env`
+    restart_attempt: *mut u8,
     c_env: Vec<*const c_char>,
     pid: pid_t,
 }
 
+impl Drop for ExecCommandLine {
+    fn drop(&mut self) {
+        for &entry in &[self.last_signal, self.restart_attempt] {
+            unsafe {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                    entry, ENTRY_SIZE)));
+            }
+        }
+    }
+}
+
+/// Command-line and environment to execute when signal happens
+///
+/// Use it with `set_handler_with` to avoid deriving command-line from
+/// `std::env`.
+pub struct CommandLine {
+    inner: Box<ExecCommandLine>,
+}
+
+impl CommandLine {
+    /// Create a command-line from program path, arguments (including
+    /// `argv[0]`) and environment
+    ///
+    /// Note: the pid of the current process is recorded here, so the
+    /// command-line should be created in the process that installs the
+    /// handler.
+    ///
+    /// Returns an error if any of the strings contains a NUL byte.
+    pub fn new<P, Ai, A, Ek, Ev, E>(program: P, args: A, environ: E)
+        -> Result<CommandLine, NulError>
+        where P: ToCString,
+              Ai: ToCString,
+              A: IntoIterator<Item=Ai>,
+              Ek: ToCString,
+              Ev: ToCString,
+              E: IntoIterator<Item=(Ek, Ev)>,
+    {
+        let env = environ.into_iter().map(|(k, v)| {
+            let mut pair = Vec::new();
+            pair.extend_from_slice(k.as_bytes());
+            pair.push(b'=');
+            pair.extend_from_slice(v.as_bytes());
+            CString::new(pair)
+        }).collect::<Result<Vec<_>, _>>()?;
+        CommandLine::build(program, args, env)
+    }
+
+    /// Create a command-line with environment specified as a list of already
+    /// joined `KEY=VALUE` entries
+    ///
+    /// Entries are passed to `execve` as is, so this is useful to preserve
+    /// environment exactly (e.g. one captured from another process).
+    ///
+    /// The only exceptions are `LAST_SIGNAL_VAR` and `RESTART_ATTEMPT_VAR`,
+    /// which are always replaced by the handler.
+    ///
+    /// Returns an error if any of the strings contains a NUL byte.
+    pub fn with_env_entries<P, Ai, A, Ei, E>(program: P, args: A, entries: E)
+        -> Result<CommandLine, NulError>
+        where P: ToCString,
+              Ai: ToCString,
+              A: IntoIterator<Item=Ai>,
+              Ei: ToCString,
+              E: IntoIterator<Item=Ei>,
+    {
+        let env = entries.into_iter().map(|x| x.try_to_cstring())
+            .collect::<Result<Vec<_>, _>>()?;
+        CommandLine::build(program, args, env)
+    }
+
+    fn build<P, Ai, A>(program: P, args: A, env: Vec<CString>)
+        -> Result<CommandLine, NulError>
+        where P: ToCString,
+              Ai: ToCString,
+              A: IntoIterator<Item=Ai>,
+    {
+        let args = args.into_iter().map(|x| x.try_to_cstring())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut c_args = args.iter().map(|x| x.as_ptr()).collect::<Vec<_>>();
+        c_args.push(null());
+        // the variables inherited from the previous process are replaced
+        let env = env.into_iter()
+            .filter(|x| !is_entry_of(x, LAST_SIGNAL_VAR) &&
+                        !is_entry_of(x, RESTART_ATTEMPT_VAR))
+            .collect::<Vec<_>>();
+        let last_signal = allocate_entry(LAST_SIGNAL_VAR);
+        let restart_attempt = allocate_entry(RESTART_ATTEMPT_VAR);
+        let mut c_env = env.iter().map(|x| x.as_ptr()).collect::<Vec<_>>();
+        c_env.push(last_signal as *const c_char);
+        c_env.push(restart_attempt as *const c_char);
+        c_env.push(null());
+        Ok(CommandLine {
+            inner: Box::new(ExecCommandLine {
+                program: program.try_to_cstring()?,
+                args,
+                c_args,
+                env,
+                last_signal,
+                restart_attempt,
+                c_env,
+                pid: unsafe { getpid() },
+            }),
+        })
+    }
+}
+
+fn is_entry_of(entry: &CString, var: &str) -> bool {
+    let entry = entry.as_bytes();
+    entry.starts_with(var.as_bytes()) && entry.get(var.len()) == Some(&b'=')
+}
+
+/// Allocate an entry of `ENTRY_SIZE` bytes with the `VAR=` prefix
+fn allocate_entry(var: &str) -> *mut u8 {
+    let mut entry = vec![0u8; ENTRY_SIZE];
+    entry[..var.len()].copy_from_slice(var.as_bytes());
+    entry[var.len()] = b'=';
+    Box::into_raw(entry.into_boxed_slice()) as *mut u8
+}
+
+impl fmt::Debug for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandLine")
+        .field("program", &self.inner.program)
+        .field("args", &self.inner.args)
+        .finish()
+    }
+}
+
 /// Sets command-line and environment to execute when signal happens
 ///
 /// If nothing is set current command-line is used.
@@ -44,7 +242,11 @@ struct ExecCommandLine {
 /// Since version v0.3.0 command-line is executed only if pid of a process
 /// matches original pid where set_handler was called. I.e. you need to
 /// set_handler again for forked process if that is desired.
+///
+/// Returns an error if any of the strings contains a NUL byte, in this case
+/// previous command-line is kept.
 pub fn set_command_line<P, Ai, A, Ek, Ev, E>(program: P, args: A, environ: E)
+    -> Result<(), NulError>
     where P: ToCString,
           Ai: ToCString,
           A: IntoIterator<Item=Ai>,
@@ -52,41 +254,63 @@ pub fn set_command_line<P, Ai, A, Ek, Ev, E>(program: P, args: A, environ: E)
           Ev: ToCString,
           E: IntoIterator<Item=(Ek, Ev)>,
 {
-    let args = args.into_iter().map(|x| x.to_cstring()).collect::<Vec<_>>();
-    let mut c_args = args.iter().map(|x| x.as_ptr()).collect::<Vec<_>>();
-    c_args.push(null());
-    let env = environ.into_iter().map(|(k, v)| {
-        let mut pair = Vec::new();
-        pair.write(k.as_bytes()).unwrap();
-        pair.push(b'=');
-        pair.write(v.as_bytes()).unwrap();
-        CString::new(pair).unwrap()
-    }).collect::<Vec<_>>();
-    let mut c_env = env.iter().map(|x| x.as_ptr()).collect::<Vec<_>>();
-    c_env.push(null());
+    replace_command_line(CommandLine::new(program, args, environ)?);
+    Ok(())
+}
+
+/// Same as `set_command_line` but environment is specified as a list of
+/// already joined `KEY=VALUE` entries
+///
+/// See `CommandLine::with_env_entries` for more info.
+pub fn set_command_line_with_env_entries<P, Ai, A, Ei, E>(program: P, args: A,
+    entries: E)
+    -> Result<(), NulError>
+    where P: ToCString,
+          Ai: ToCString,
+          A: IntoIterator<Item=Ai>,
+          Ei: ToCString,
+          E: IntoIterator<Item=Ei>,
+{
+    replace_command_line(
+        CommandLine::with_env_entries(program, args, entries)?);
+    Ok(())
+}
+
+fn replace_command_line(command_line: CommandLine) {
     unsafe {
-        if EXEC_COMMAND_LINE != null() {
-            transmute::<_, Box<ExecCommandLine>>(EXEC_COMMAND_LINE);
+        if !EXEC_COMMAND_LINE.is_null() {
+            drop(Box::from_raw(EXEC_COMMAND_LINE as *mut ExecCommandLine));
         }
-        let new = Box::new(ExecCommandLine {
-            program: program.to_cstring(),
-            args: args,
-            c_args: c_args,
-            env: env,
-            c_env: c_env,
-            pid: getpid(),
-        });
-
-        EXEC_COMMAND_LINE = &*new;
-        forget(new);
+        EXEC_COMMAND_LINE = Box::into_raw(command_line.inner);
     }
 }
 
 extern "C" fn exec_handler(sig:c_int) {
+    // Another fatal signal arrived (in other thread or because `execve`
+    // failed) while we are already handling one. Don't try to exec twice,
+    // just exit the same way as the default action would (mostly).
+    if HANDLER_ENTERED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(128 + sig) };
+    }
     unsafe {
         if getpid() != (*EXEC_COMMAND_LINE).pid {
             panic!("Early signal {:?} after fork", sig);
         } else {
+            last_signal::record(sig);
+            write_last_signal((*EXEC_COMMAND_LINE).last_signal, sig);
+            write_restart_attempt(
+                &mut *(EXEC_COMMAND_LINE as *mut ExecCommandLine));
+            if RESTART_DELAY.enabled.load(Ordering::SeqCst) {
+                restart_delay(&RESTART_DELAY);
+            }
+            if let Some(hook) = PRE_EXEC_HOOK {
+                hook();
+            }
+            if !PIDFILE.is_null() {
+                write_pidfile(&*PIDFILE);
+            }
+            #[cfg(test)]
+            let execve = EXEC;
             let err = execve((*EXEC_COMMAND_LINE).program.as_ptr(),
                        (*EXEC_COMMAND_LINE).c_args.as_ptr(),
                        (*EXEC_COMMAND_LINE).c_env.as_ptr());
@@ -95,6 +319,230 @@ extern "C" fn exec_handler(sig:c_int) {
     }
 }
 
+/// The `SA_SIGINFO` variant set by `set_crash_handler`
+extern "C" fn crash_handler(sig: c_int, info: *mut siginfo_t,
+    context: *mut c_void)
+{
+    let fd = CRASH_REPORT_FD.load(Ordering::SeqCst);
+    if fd >= 0 && !HANDLER_ENTERED.load(Ordering::SeqCst) {
+        unsafe { write_report(fd, sig, info, context) };
+    }
+    exec_handler(sig);
+}
+
+/// Fill in the `LAST_SIGNAL_VAR` entry, this is async-signal-safe
+///
+/// The time is the one just stored by `last_signal::record`.
+unsafe fn write_last_signal(entry: *mut u8, sig: c_int) {
+    let entry = slice::from_raw_parts_mut(entry, ENTRY_SIZE);
+    let since_epoch = last_signal::last_signal()
+        .and_then(|(_, time)| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut pos = LAST_SIGNAL_VAR.len() + 1;
+    pos = write_decimal(entry, pos, sig as u64, 1);
+    entry[pos] = b':';
+    pos = write_decimal(entry, pos + 1, since_epoch.as_secs(), 1);
+    entry[pos] = b'.';
+    pos = write_decimal(entry, pos + 1,
+                        since_epoch.subsec_nanos() as u64, 9);
+    entry[pos] = 0;
+}
+
+/// Fill in the `RESTART_ATTEMPT_VAR` entry, this is async-signal-safe
+///
+/// The entry is left out of the environment if `set_restart_backoff` was
+/// never called.
+unsafe fn write_restart_attempt(command_line: &mut ExecCommandLine) {
+    let slot = command_line.c_env.len() - 2;
+    if !RESTART_DELAY.enabled.load(Ordering::SeqCst) {
+        command_line.c_env[slot] = null();
+        return;
+    }
+    let entry = slice::from_raw_parts_mut(command_line.restart_attempt,
+                                          ENTRY_SIZE);
+    let attempt = RESTART_DELAY.next_attempt.load(Ordering::SeqCst);
+    let started = RESTART_DELAY.started.load(Ordering::SeqCst);
+    let mut pos = RESTART_ATTEMPT_VAR.len() + 1;
+    pos = write_decimal(entry, pos, attempt as u64, 1);
+    entry[pos] = b':';
+    pos = write_decimal(entry, pos + 1, started, 1);
+    entry[pos] = 0;
+    command_line.c_env[slot] = command_line.restart_attempt as *const c_char;
+}
+
+/// Write `value` zero-padded to `width` digits, returns the end position
+fn write_decimal(buf: &mut [u8], pos: usize, mut value: u64, width: usize)
+    -> usize
+{
+    let mut digits = [b'0'; 20];
+    let mut len = 0;
+    while value > 0 || len < width {
+        digits[digits.len() - 1 - len] = b'0' + (value % 10) as u8;
+        value /= 10;
+        len += 1;
+    }
+    buf[pos..pos + len].copy_from_slice(&digits[digits.len() - len..]);
+    pos + len
+}
+
+/// Rewrite the pidfile in place, this is async-signal-safe
+unsafe fn write_pidfile(pidfile: &Pidfile) {
+    let data = &pidfile.contents;
+    if libc::lseek(pidfile.fd, 0, libc::SEEK_SET) == 0 &&
+        libc::ftruncate(pidfile.fd, 0) == 0
+    {
+        let mut written = 0;
+        while written < data.len() {
+            let res = libc::write(pidfile.fd,
+                data[written..].as_ptr() as *const c_void,
+                data.len() - written);
+            if res < 0 {
+                if Errno::last() == Errno::EINTR {
+                    continue;
+                }
+                break;
+            }
+            written += res as usize;
+        }
+        libc::fsync(pidfile.fd);
+    }
+    libc::close(pidfile.fd);
+}
+
+/// Sleep before the restart, this is async-signal-safe
+unsafe fn restart_delay(restart: &RestartDelay) {
+    let reset_after = restart.reset_after.load(Ordering::SeqCst);
+    let started = restart.started.load(Ordering::SeqCst);
+    let healthy = reset_after != NO_RESET &&
+        monotonic_now().saturating_sub(Duration::from_nanos(started))
+            >= Duration::from_nanos(reset_after);
+    let delay = if healthy {
+        restart.first_delay.load(Ordering::SeqCst)
+    } else {
+        restart.delay.load(Ordering::SeqCst)
+    };
+    let mut left = timespec(Duration::from_nanos(delay));
+    let mut rem: libc::timespec = zeroed();
+    while libc::nanosleep(&left, &mut rem) != 0 {
+        if Errno::last() != Errno::EINTR {
+            break;
+        }
+        left = rem;
+    }
+}
+
+fn monotonic_now() -> Duration {
+    let mut ts: libc::timespec = unsafe { zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+fn timespec(duration: Duration) -> libc::timespec {
+    let mut ts: libc::timespec = unsafe { zeroed() };
+    ts.tv_sec = duration.as_secs() as libc::time_t;
+    ts.tv_nsec = duration.subsec_nanos() as _;
+    ts
+}
+
+/// Returns the signal that made the previous process execute this one
+///
+/// This is the signal number and the time it was received, as passed in
+/// `LAST_SIGNAL_VAR` by the handler of the previous process. Returns `None`
+/// if the process wasn't executed by the handler.
+pub fn previous_signal() -> Option<(c_int, SystemTime)> {
+    let value = env::var(LAST_SIGNAL_VAR).ok()?;
+    let (sig, time) = value.split_once(':')?;
+    let (seconds, nanoseconds) = time.split_once('.')?;
+    let since_epoch = Duration::new(seconds.parse().ok()?,
+                                    nanoseconds.parse().ok()?);
+    Some((sig.parse().ok()?, UNIX_EPOCH + since_epoch))
+}
+
+/// Wait before executing the command-line, growing the delay on each crash
+///
+/// The handler sleeps for `backoff.delay(attempt)` before running the
+/// pre-exec hook and `execve`, so a program crashing right after the start
+/// doesn't restart in a tight loop. The attempt number is passed to the
+/// new process in the `RESTART_ATTEMPT_VAR` environment variable, which is
+/// added to the command-line by the handler (the environment of the current
+/// process isn't changed). So this function may be called either before or
+/// after `set_handler`, and should be called early in the new process too.
+///
+/// If the process ran for `backoff.reset_threshold()` before the signal,
+/// the initial delay is used and the new process starts from attempt zero.
+///
+/// The delay (including jitter) is computed here, the handler only calls
+/// `clock_gettime` and `nanosleep`. Note that other threads keep running
+/// while the crashed one sleeps. Returns the current attempt number.
+///
+/// Calling it again replaces the delay. If the handler runs at the same
+/// time, it may use a mix of the old and the new values.
+pub fn set_restart_backoff(backoff: Backoff) -> u32 {
+    let now = monotonic_now();
+    let attempt = env::var(RESTART_ATTEMPT_VAR).ok()
+        .and_then(|value| {
+            let mut parts = value.splitn(2, ':');
+            let attempt = parts.next()?.parse::<u32>().ok()?;
+            let started = parts.next()?.parse::<u64>().ok()?;
+            Some((attempt, Duration::from_nanos(started)))
+        })
+        .map(|(attempt, started)| {
+            match backoff.reset_threshold() {
+                Some(reset) if now.saturating_sub(started) >= reset => 0,
+                _ => attempt,
+            }
+        })
+        .unwrap_or(0);
+    let nanos = |d: Duration| d.as_nanos().min(NO_RESET as u128 - 1) as u64;
+    let restart = &RESTART_DELAY;
+    restart.delay.store(nanos(backoff.delay(attempt)), Ordering::SeqCst);
+    restart.first_delay.store(nanos(backoff.delay(0)), Ordering::SeqCst);
+    let reset_after = backoff.reset_threshold().map_or(NO_RESET, nanos);
+    restart.reset_after.store(reset_after, Ordering::SeqCst);
+    restart.started.store(nanos(now), Ordering::SeqCst);
+    restart.next_attempt.store(attempt.saturating_add(1), Ordering::SeqCst);
+    restart.enabled.store(true, Ordering::SeqCst);
+    attempt
+}
+
+/// Rewrite the pidfile before executing the command-line
+///
+/// The `fd` must be a descriptor of the pidfile opened for writing in
+/// advance (opening files in a signal handler isn't wise). In the handler
+/// the file is truncated, the pid of the process is written, then the file
+/// is `fsync`ed and the descriptor is closed, so it doesn't leak into the
+/// new process. The pid is the one of the process calling this function,
+/// which is still the same after `execve`.
+///
+/// Everything is done using async-signal-safe functions, errors are
+/// ignored since there is nothing useful to do with them in the handler.
+/// Replaces the previously set pidfile, the old descriptor is not closed.
+pub fn set_pidfile(fd: RawFd) {
+    let pidfile = Box::new(Pidfile {
+        fd,
+        contents: format!("{}\n", unsafe { getpid() }).into_bytes(),
+    });
+    unsafe {
+        if !PIDFILE.is_null() {
+            drop(Box::from_raw(PIDFILE as *mut Pidfile));
+        }
+        PIDFILE = Box::into_raw(pidfile);
+    }
+}
+
+/// Set a function that is called in the handler right before `execve`
+///
+/// This is called before the pidfile (see `set_pidfile`) is written and
+/// after the restart delay (see `set_restart_backoff`).
+///
+/// # Safety
+///
+/// The hook runs in the signal handler, so it must only call
+/// async-signal-safe functions (see `signal-safety(7)`).
+pub unsafe fn set_pre_exec_hook(hook: fn()) {
+    PRE_EXEC_HOOK = Some(hook);
+}
+
 
 /// Set a handler for multiple signals. If no `set_command_line` was called
 /// before this function the command-line is set from ``std::env``
@@ -115,25 +563,104 @@ extern "C" fn exec_handler(sig:c_int) {
 /// Since version v0.3.0 command-line is executed only if pid of a process
 /// matches original pid where set_handler was called. I.e. you need to
 /// set_handler again for forked process if that is desired.
+///
+/// The signal list is validated before any disposition is changed: if it
+/// contains `SIGKILL` or `SIGSTOP` (which can't be caught) the function
+/// returns `Error::Uncatchable` and no handlers are installed. This is
+/// best effort: `sigaction` may still fail for some other signal after the
+/// preceding ones were installed.
 pub fn set_handler(signals: &[Signal], avoid_race_condition: bool)
-    -> nix::Result<()>
+    -> Result<(), Error>
 {
+    check_catchable(signals)?;
+    set_default_command_line();
+    install(signals, avoid_race_condition, SaFlags::empty(),
+            SigHandler::Handler(exec_handler))
+}
+
+/// Options for `set_handler_with`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    avoid_race_condition: bool,
+    one_shot: bool,
+}
+
+impl Options {
+    /// Default options
+    pub fn new() -> Options {
+        Options::default()
+    }
+
+    /// Block the signals in the executed process, see `set_handler` for
+    /// the explanation
+    pub fn avoid_race_condition(mut self, value: bool) -> Options {
+        self.avoid_race_condition = value;
+        self
+    }
+
+    /// Reset the signal disposition to default when the handler fires
+    ///
+    /// This sets `SA_RESETHAND`: if the same signal is received again
+    /// before the command is executed (or if the exec fails and the signal
+    /// is raised again) the default action happens, e.g. the process is
+    /// killed, instead of running the handler again.
+    pub fn one_shot(mut self, value: bool) -> Options {
+        self.one_shot = value;
+        self
+    }
+}
+
+/// Set a handler for multiple signals executing the specified command-line
+///
+/// Unlike `set_handler` this function never derives command-line from
+/// `std::env` (which requires `/proc` to find out current executable on
+/// linux), so it's suitable for sandboxed environments. The command-line
+/// replaces any previously set one.
+///
+/// Signals are validated like in `set_handler` before command-line is
+/// replaced.
+pub fn set_handler_with(signals: &[Signal], command_line: CommandLine,
+    options: Options)
+    -> Result<(), Error>
+{
+    check_catchable(signals)?;
+    replace_command_line(command_line);
+    let flags = if options.one_shot {
+        SaFlags::SA_RESETHAND
+    } else {
+        SaFlags::empty()
+    };
+    install(signals, options.avoid_race_condition, flags,
+            SigHandler::Handler(exec_handler))
+}
+
+fn set_default_command_line() {
     unsafe {
-        if EXEC_COMMAND_LINE == null() {
-            set_command_line(current_exe().unwrap(), args_os(), vars_os());
+        if EXEC_COMMAND_LINE.is_null() {
+            // strings that come from the OS can't contain NUL bytes
+            set_command_line(current_exe().unwrap(), args_os(), vars_os())
+                .unwrap();
         }
+    }
+}
+
+fn install(signals: &[Signal], avoid_race_condition: bool, flags: SaFlags,
+    handler: SigHandler)
+    -> Result<(), Error>
+{
+    check_catchable(signals)?;
+    unsafe {
         let mut sigset = SigSet::empty();
         if avoid_race_condition {
             for &sig in signals {
-                sigset.add(sig);
+                sigset.add(sig.into());
             }
         }
         let mut res = Ok(());
         for &sig in signals {
             res = res.and_then(|()| {
-                try!(sigaction(sig, &SigAction::new(
-                    SigHandler::Handler(exec_handler),
-                    SaFlags::empty(), sigset)));
+                sigaction(sig.into(), &SigAction::new(handler, flags, sigset))
+                    .map_err(|e| Error::sigaction(sig, e))?;
                 Ok(())
             });
         }
@@ -144,3 +671,159 @@ pub fn set_handler(signals: &[Signal], avoid_race_condition: bool)
         res
     }
 }
+
+/// Check whether the exec handler is currently installed for the signal
+///
+/// This allows to detect that some other library has overwritten the handler
+/// set by `set_handler` or `set_crash_handler`.
+pub fn is_handler_installed(sig: Signal) -> nix::Result<bool> {
+    unsafe {
+        let mut current: libc::sigaction = zeroed();
+        if libc::sigaction(to_raw(sig), null(), &mut current) != 0 {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        let handler = exec_handler as extern "C" fn(c_int);
+        let crash_handler = crash_handler as
+            extern "C" fn(c_int, *mut siginfo_t, *mut c_void);
+        Ok(current.sa_sigaction == handler as usize ||
+           current.sa_sigaction == crash_handler as usize)
+    }
+}
+
+/// Returns the signals that are sent to the process when it crashes
+///
+/// These are `SIGSEGV`, `SIGBUS`, `SIGABRT`, `SIGFPE` and `SIGILL`, same as
+/// `groups::FATAL_SIGNALS`.
+pub fn fatal_signals() -> &'static [Signal] {
+    FATAL_SIGNALS
+}
+
+/// Set a handler that re-executes command-line when the process crashes
+///
+/// This is equivalent to `set_handler(fatal_signals(), true)` but the
+/// handler runs on an alternate signal stack, so it also works on stack
+/// overflow (which is reported as `SIGSEGV`, when there is no room on the
+/// normal stack to run a handler).
+///
+/// The alternate stack is per-thread, so it's only set up for the calling
+/// thread (unless the thread already has one). Crashes in other threads still
+/// run the handler, but on the thread's own stack.
+///
+/// The handler is installed with `SA_SIGINFO`, so it can write crash
+/// diagnostics first, see `set_crash_report`.
+pub fn set_crash_handler() -> Result<(), Error> {
+    set_default_command_line();
+    set_alt_stack().map_err(Error::alt_stack)?;
+    install(fatal_signals(), true, SaFlags::SA_ONSTACK,
+            SigHandler::SigAction(crash_handler))
+}
+
+/// Write crash diagnostics to `fd` before executing the command-line
+///
+/// The handler set by `set_crash_handler` writes a line with the signal,
+/// the fault address and registers, see `crash_report::write_report`. The
+/// descriptor must be opened in advance (usually stderr or a log file) and
+/// stay open for the lifetime of the process, a negative `fd` turns the
+/// report off. May be called before or after `set_crash_handler`.
+pub fn set_crash_report(fd: RawFd) {
+    CRASH_REPORT_FD.store(fd, Ordering::SeqCst);
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::ptr::null_mut;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use libc::{self, c_char, c_int, c_void};
+    use nix::sys::signal::SigSet;
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::unistd::{close, fork, pipe, read, ForkResult};
+
+    use Signal::SIGUSR1;
+    use super::{set_handler_with, CommandLine, Options, EXEC};
+
+    static RESULT_FD: AtomicI32 = AtomicI32::new(-1);
+
+    /// Writes the arguments to `RESULT_FD` NUL-separated, and exits
+    unsafe extern "C" fn mock_exec(program: *const c_char,
+        argv: *const *const c_char, envp: *const *const c_char)
+        -> c_int
+    {
+        let fd = RESULT_FD.load(Ordering::SeqCst);
+        write_str(fd, program);
+        write_list(fd, argv);
+        write_list(fd, envp);
+        libc::_exit(0);
+    }
+
+    unsafe fn write_list(fd: c_int, mut list: *const *const c_char) {
+        while !(*list).is_null() {
+            write_str(fd, *list);
+            list = list.add(1);
+        }
+        write_str(fd, b"--\0".as_ptr() as *const c_char);
+    }
+
+    unsafe fn write_str(fd: c_int, value: *const c_char) {
+        libc::write(fd, value as *const c_void, libc::strlen(value) + 1);
+    }
+
+    #[test]
+    fn handler_executes_command_line() {
+        let (rd, wr) = pipe().unwrap();
+        let mut command_line = CommandLine::new("/bin/program",
+            ["program", "with spaces"],
+            vec![("KEY", "value"), ("OTHER", "")]).unwrap();
+        let mut sigset = SigSet::empty();
+        sigset.add(SIGUSR1.into());
+        // the handler can't return, so it runs in a child process; the
+        // test harness is multithreaded, so the child only makes
+        // async-signal-safe calls (nothing is allocated or locked)
+        match fork().unwrap() {
+            ForkResult::Child => unsafe {
+                libc::close(rd);
+                RESULT_FD.store(wr, Ordering::SeqCst);
+                EXEC = mock_exec;
+                command_line.inner.pid = libc::getpid();
+                libc::pthread_sigmask(libc::SIG_UNBLOCK, sigset.as_ref(),
+                                      null_mut());
+                if set_handler_with(&[SIGUSR1], command_line, Options::new())
+                    .is_ok()
+                {
+                    libc::raise(libc::SIGUSR1);
+                }
+                libc::_exit(1);
+            },
+            ForkResult::Parent { child } => {
+                close(wr).unwrap();
+                let mut output = Vec::new();
+                let mut buf = [0u8; 1024];
+                loop {
+                    match read(rd, &mut buf).unwrap() {
+                        0 => break,
+                        n => output.extend_from_slice(&buf[..n]),
+                    }
+                }
+                close(rd).unwrap();
+                assert_eq!(waitpid(child, None).unwrap(),
+                           WaitStatus::Exited(child, 0));
+                let mut parts = output.split(|&b| b == 0)
+                    .map(|part| String::from_utf8_lossy(part).into_owned())
+                    .collect::<Vec<_>>();
+                let last_signal = parts.remove(6);
+                assert_eq!(parts, [
+                    "/bin/program",
+                    "program", "with spaces", "--",
+                    "KEY=value", "OTHER=", "--",
+                    "",
+                ]);
+                let prefix = format!("SIGNAL_LAST_SIGNAL={}:", libc::SIGUSR1);
+                let value = last_signal.strip_prefix(&prefix).unwrap();
+                let (seconds, nanoseconds) = value.split_once('.').unwrap();
+                assert!(seconds.parse::<u64>().unwrap() > 0);
+                assert_eq!(nanoseconds.len(), 9);
+            }
+        }
+    }
+}
@@ -1,8 +1,22 @@
-use std::ffi::{CString, OsStr};
+use std::ffi::{CString, OsStr, NulError};
 use std::os::unix::ffi::OsStrExt;
+use std::mem::zeroed;
+use std::ptr::{null, null_mut};
+use std::cmp;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::time::Instant;
+
+use libc::{self, sigaltstack, stack_t, c_void, SS_DISABLE};
+use nix;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
 
 pub trait ToCString {
     fn to_cstring(&self) -> CString;
+    fn try_to_cstring(&self) -> Result<CString, NulError>;
     fn as_bytes(&self) -> &[u8];
 }
 
@@ -11,8 +25,92 @@ impl<T:AsRef<OsStr>> ToCString for T {
         CString::new(self.as_ref().as_bytes())
         .unwrap()
     }
+    fn try_to_cstring(&self) -> Result<CString, NulError> {
+        CString::new(self.as_ref().as_bytes())
+    }
     fn as_bytes(&self) -> &[u8] {
         self.as_ref().as_bytes()
     }
 }
 
+
+/// Read a single signal from non-blocking signalfd
+///
+/// Returns `None` if there is no signal pending. The kernel only returns
+/// whole records, so a short read (including end of file) is reported as
+/// `EIO`.
+#[cfg(target_os = "linux")]
+pub fn read_signalfd(fd: RawFd) -> nix::Result<Option<libc::signalfd_siginfo>>
+{
+    unsafe {
+        let mut info: libc::signalfd_siginfo = mem::zeroed();
+        let size = mem::size_of::<libc::signalfd_siginfo>();
+        let res = libc::read(fd, &mut info as *mut _ as *mut libc::c_void,
+                             size);
+        if res < 0 {
+            match Errno::last() {
+                Errno::EAGAIN => Ok(None),
+                errno => Err(nix::Error::Sys(errno)),
+            }
+        } else if res as usize == size {
+            Ok(Some(info))
+        } else {
+            Err(nix::Error::Sys(Errno::EIO))
+        }
+    }
+}
+
+/// Wait until file descriptor is readable or deadline is reached
+///
+/// Returns `false` on timeout. `EINTR` is returned as an error. Timeout is
+/// rounded up to milliseconds, so this never returns before the deadline.
+pub fn wait_readable(fd: RawFd, deadline: Option<Instant>)
+    -> nix::Result<bool>
+{
+    let timeout = match deadline {
+        Some(deadline) => {
+            let dur = deadline.saturating_duration_since(Instant::now());
+            let ms = dur.as_nanos().div_ceil(1_000_000);
+            cmp::min(ms, libc::c_int::MAX as u128) as libc::c_int
+        }
+        None => -1,
+    };
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    Ok(poll(&mut fds, timeout)? > 0)
+}
+
+const ALT_STACK_SIZE: usize = 65536;
+
+/// Set up an alternate signal stack for the current thread if it has none
+pub fn set_alt_stack() -> nix::Result<()> {
+    unsafe {
+        let mut old: stack_t = zeroed();
+        if sigaltstack(null(), &mut old) != 0 {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        if old.ss_flags & SS_DISABLE == 0 {
+            // already have one, probably set up by the runtime
+            return Ok(());
+        }
+        // The stack is leaked intentionally, the handler may run at any
+        // moment until the thread exits
+        let stack = vec![0u8; ALT_STACK_SIZE].into_boxed_slice();
+        let new = stack_t {
+            ss_sp: Box::into_raw(stack) as *mut c_void,
+            ss_flags: 0,
+            ss_size: ALT_STACK_SIZE,
+        };
+        if sigaltstack(&new, null_mut()) != 0 {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        Ok(())
+    }
+}
+
+/// Convert the error of a system call into `io::Error`
+pub fn io_error(e: nix::Error) -> io::Error {
+    match e {
+        nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        e => io::Error::other(e),
+    }
+}
@@ -0,0 +1,41 @@
+//! Forking with correct signal discipline
+//!
+//! If a signal arrives right after `fork()` the child may run a signal
+//! handler inherited from the parent, which is usually not prepared to run
+//! in the child (e.g. the state it uses is not consistent there). So the
+//! right way is to block all signals, fork, reset signal dispositions in the
+//! child and only then unblock signals.
+
+use libc::pid_t;
+use nix;
+use nix::sys::signal::{pthread_sigmask, SigmaskHow, SigSet};
+use nix::unistd::{fork, ForkResult};
+
+
+/// Fork the process with all signals blocked around the `fork()`
+///
+/// In the child `child_setup` is called while all signals are still
+/// blocked, it should reset signal dispositions (and do other things
+/// required before any signal may be delivered). Then the original signal
+/// mask is restored in the child too.
+///
+/// Like `fork()` returns the pid of the child in the parent and zero in the
+/// child. The signal mask of the parent is restored in both cases, including
+/// when `fork()` fails.
+///
+/// Note that in a multithreaded program, only async-signal-safe functions
+/// may be called in `child_setup`.
+pub fn fork_safely<F: FnOnce()>(child_setup: F) -> nix::Result<pid_t> {
+    let mut oldset = SigSet::empty();
+    pthread_sigmask(SigmaskHow::SIG_SETMASK,
+                    Some(&SigSet::all()), Some(&mut oldset))?;
+    let result = fork();
+    if let Ok(ForkResult::Child) = result {
+        child_setup();
+    }
+    pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&oldset), None)?;
+    match result? {
+        ForkResult::Parent { child } => Ok(child.as_raw()),
+        ForkResult::Child => Ok(0),
+    }
+}
@@ -0,0 +1,85 @@
+//! Commonly used groups of signals
+//!
+//! These are meant to be passed to `Trap::trap` and similar functions, to
+//! document the intent at the call site and to avoid forgetting some signal.
+//!
+//! All signals listed here are available on every supported platform.
+
+use Signal;
+use Signal::*;
+
+
+/// Signals requesting the process to terminate
+///
+/// These are `SIGTERM`, `SIGINT`, `SIGQUIT` and `SIGHUP`, i.e. all the
+/// signals a process is expected to shut down on when they aren't handled.
+/// Note: daemons often use `SIGHUP` for reloading (see `RELOAD_SIGNALS`), so
+/// `classify` doesn't treat it as a termination request.
+pub const TERMINATION_SIGNALS: &[Signal] = &[
+    SIGTERM, SIGINT, SIGQUIT, SIGHUP,
+];
+
+/// Signals sent to the process when it crashes
+///
+/// These are `SIGSEGV`, `SIGBUS`, `SIGABRT`, `SIGFPE` and `SIGILL`. They
+/// are usually raised synchronously by the faulting thread, so they can't be
+/// received by a `Trap`: they are meant for crash handlers (see
+/// `exec_handler::set_crash_handler` and `crash_report`).
+pub const FATAL_SIGNALS: &[Signal] = &[
+    SIGSEGV, SIGBUS, SIGABRT, SIGFPE, SIGILL,
+];
+
+/// Signals conventionally used to reload configuration (`SIGHUP`)
+pub const RELOAD_SIGNALS: &[Signal] = &[SIGHUP];
+
+/// Job control signals (`SIGTSTP`, `SIGTTIN`, `SIGTTOU`, `SIGCONT`)
+///
+/// Note: `SIGSTOP` is not included because it can't be caught.
+pub const JOB_CONTROL_SIGNALS: &[Signal] = &[SIGTSTP, SIGTTIN, SIGTTOU, SIGCONT];
+
+/// Signals reserved for application use (`SIGUSR1`, `SIGUSR2`)
+pub const USER_SIGNALS: &[Signal] = &[SIGUSR1, SIGUSR2];
+
+/// Signals a proxy or an init process passes on to its child
+///
+/// These are `TERMINATION_SIGNALS`, `USER_SIGNALS`, `SIGWINCH`, `SIGALRM`
+/// and `SIGCONT`. `SIGCHLD` is about the proxy's own children, and the
+/// stop signals are left out, as stopping the child without the proxy
+/// confuses the shell's job control.
+pub const FORWARDED_SIGNALS: &[Signal] = &[
+    SIGTERM, SIGINT, SIGQUIT, SIGHUP, SIGUSR1, SIGUSR2,
+    SIGWINCH, SIGALRM, SIGCONT,
+];
+
+/// The intent of a signal, as conventionally used by process supervisors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalClass {
+    /// Request to terminate the process (`SIGTERM`, `SIGINT`, `SIGQUIT`)
+    Terminate,
+    /// A child process changed its state (`SIGCHLD`)
+    ChildStatus,
+    /// Request to reload configuration (`RELOAD_SIGNALS`)
+    Reload,
+    /// Application-defined signals (`USER_SIGNALS`)
+    UserDefined,
+    /// Job control signals (`JOB_CONTROL_SIGNALS` and `SIGSTOP`)
+    JobControl,
+    /// Any other signal
+    Other,
+}
+
+/// Returns the class of the signal
+///
+/// This allows supervisor loops to branch on intent instead of matching on
+/// individual signals.
+pub fn classify(sig: Signal) -> SignalClass {
+    match sig {
+        SIGTERM | SIGINT | SIGQUIT => SignalClass::Terminate,
+        SIGCHLD => SignalClass::ChildStatus,
+        SIGSTOP => SignalClass::JobControl,
+        _ if RELOAD_SIGNALS.contains(&sig) => SignalClass::Reload,
+        _ if USER_SIGNALS.contains(&sig) => SignalClass::UserDefined,
+        _ if JOB_CONTROL_SIGNALS.contains(&sig) => SignalClass::JobControl,
+        _ => SignalClass::Other,
+    }
+}
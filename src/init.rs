@@ -0,0 +1,117 @@
+//! Running as the init process of a container
+//!
+//! The first process of a container has PID 1, and it's special in two
+//! ways. Orphaned processes are reparented to it, so it has to reap them or
+//! they stay zombies forever. And the kernel doesn't run the default action
+//! of a signal for it, so a program that doesn't handle `SIGTERM` isn't
+//! terminated by `docker stop` and is killed after the timeout instead.
+//!
+//! `run_as_init()` spawns the main program as a child and acts as a minimal
+//! init for it, like `tini` or `dumb-init`: the signals are forwarded to
+//! the child, all the exited processes are reaped continuously, and the
+//! status of the child is returned when it exits. The init is expected to
+//! exit right away with `proxy::exit_with()`, after which the kernel kills
+//! the other processes of the container.
+//!
+//! A program that runs as PID 1 on its own (without an init) should call
+//! `emulate_default_actions()` early, so that `SIGTERM` and `SIGINT`
+//! terminate it in a container just as they do elsewhere.
+
+use std::io;
+use std::mem::zeroed;
+use std::ptr::null;
+use std::process::Command;
+
+use libc::{self, c_int};
+use nix;
+use nix::errno::Errno;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::getpid;
+#[cfg(target_os = "linux")]
+use subreaper::is_child_subreaper;
+
+use proxy::Proxy;
+use {check_catchable, to_raw, Error, Signal};
+
+
+/// Returns `true` if the current process is PID 1
+///
+/// This is the case for the first process of a container (i.e. of a pid
+/// namespace), as well as for the real init of the system.
+pub fn is_init() -> bool {
+    getpid().as_raw() == 1
+}
+
+/// Spawn the command and supervise it like an init process
+///
+/// Traps `groups::FORWARDED_SIGNALS` and `SIGCHLD`, forwards the signals to
+/// the child and returns its status when it exits. When the process is
+/// PID 1 (see `is_init()`) or a child subreaper (see `subreaper`) every
+/// child is reaped, including the adopted orphans. Otherwise there are no
+/// orphans to adopt, and only the spawned child is reaped, so this works as
+/// a plain `proxy::Proxy` when the same binary runs outside of a container.
+///
+/// For other settings (e.g. to signal the whole process group) use
+/// `Proxy` with `reap_orphans(adopts_orphans())` directly.
+pub fn run_as_init(command: &mut Command) -> io::Result<WaitStatus> {
+    Proxy::new().reap_orphans(adopts_orphans()).run(command)
+}
+
+/// Returns `true` if orphaned processes are reparented to this process
+///
+/// This is the case for PID 1 and, on linux, for a child subreaper.
+pub fn adopts_orphans() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        if is_child_subreaper().unwrap_or(false) {
+            return true;
+        }
+    }
+    is_init()
+}
+
+/// Terminate on the signals when running as PID 1, as outside a container
+///
+/// The kernel never runs the default action of a signal for PID 1 (the
+/// signals are dropped instead), so for each of the signals that has the
+/// default disposition, a handler is installed that exits the process with
+/// the code `128 + signal`. That's also what the container runtime reports
+/// for a process killed by the signal. Signals that are ignored or already
+/// handled (including by a `Trap`) are left alone, and a `Trap` created
+/// later takes precedence over these handlers while it's active.
+///
+/// Does nothing and returns `false` if the process isn't PID 1. Usually
+/// called with `groups::TERMINATION_SIGNALS`. Note that the handler exits
+/// without running destructors or flushing buffers, like the default
+/// action would.
+pub fn emulate_default_actions(signals: &[Signal]) -> Result<bool, Error> {
+    check_catchable(signals)?;
+    if !is_init() {
+        return Ok(false);
+    }
+    for &sig in signals {
+        if !has_default_action(sig).map_err(|e| Error::sigaction(sig, e))? {
+            continue;
+        }
+        let action = SigAction::new(SigHandler::Handler(exit_handler),
+            SaFlags::empty(), SigSet::empty());
+        unsafe { sigaction(sig.into(), &action) }
+            .map_err(|e| Error::sigaction(sig, e))?;
+    }
+    Ok(true)
+}
+
+fn has_default_action(sig: Signal) -> nix::Result<bool> {
+    unsafe {
+        let mut current: libc::sigaction = zeroed();
+        if libc::sigaction(to_raw(sig), null(), &mut current) != 0 {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        Ok(current.sa_sigaction == libc::SIG_DFL)
+    }
+}
+
+extern "C" fn exit_handler(sig: c_int) {
+    unsafe { libc::_exit(128 + sig) };
+}
@@ -0,0 +1,65 @@
+//! Waiting for signals with `kqueue` on macOS and the BSDs
+//!
+//! These systems have no `sigtimedwait`, but `EVFILT_SIGNAL` reports
+//! signals sent to the process even if they are blocked. The event doesn't
+//! consume the signal, so it's taken with `sigwait` afterwards.
+
+use std::os::unix::io::RawFd;
+use std::time::Instant;
+
+use libc::{self, timespec};
+use nix;
+use nix::sys::event::{kqueue, kevent_ts, KEvent};
+use nix::sys::event::{EventFilter, EventFlag, FilterFlag};
+use nix::sys::signal::SigSet;
+use nix::unistd;
+
+use {to_raw, Signal};
+
+
+/// A `kqueue` watching for the signals of the set
+pub struct SignalQueue {
+    fd: RawFd,
+}
+
+impl SignalQueue {
+    /// Create the queue and register the signals
+    ///
+    /// Only signals sent after this call are reported, so pending signals
+    /// should be checked afterwards.
+    pub fn new(sigset: &SigSet) -> nix::Result<SignalQueue> {
+        let queue = SignalQueue { fd: kqueue()? };
+        let changes = Signal::iterator()
+            .filter(|&sig| sigset.contains(sig.into()))
+            .map(|sig| {
+                event(to_raw(sig) as libc::uintptr_t, EventFlag::EV_ADD)
+            })
+            .collect::<Vec<_>>();
+        kevent_ts(queue.fd, &changes, &mut [], None)?;
+        Ok(queue)
+    }
+
+    /// Wait until one of the signals is sent or deadline is reached
+    ///
+    /// Returns `false` on timeout. `EINTR` is returned as an error.
+    pub fn wait(&self, deadline: Instant) -> nix::Result<bool> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let tm = timespec {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+        let mut events = [event(0, EventFlag::empty())];
+        Ok(kevent_ts(self.fd, &[], &mut events, Some(tm))? > 0)
+    }
+}
+
+fn event(ident: libc::uintptr_t, flags: EventFlag) -> KEvent {
+    KEvent::new(ident, EventFilter::EVFILT_SIGNAL, flags,
+                FilterFlag::empty(), 0, 0)
+}
+
+impl Drop for SignalQueue {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
@@ -0,0 +1,116 @@
+//! Records the last signal received by the process
+//!
+//! This leaves a breadcrumb for crash reporting: `record()` stores signal
+//! number and a timestamp into static atomics. The handler of the
+//! `exec_handler` module records every signal it receives, custom handlers
+//! (e.g. ones set by `Trap::with_handler`) may call `record()` themselves.
+//! For signals without a handler, `install()` sets a tiny one that records
+//! the signal and then lets its default action happen.
+//!
+//! The record is only kept in memory of the current process, so it's useful
+//! for signals that don't terminate the process, or for code running after
+//! them in the same process (e.g. other handlers). The exec
+//! handler passes it on to the executed process in the
+//! `exec_handler::LAST_SIGNAL_VAR` environment variable, read it back there
+//! with `exec_handler::previous_signal()`.
+
+use std::sync::atomic::{AtomicI32, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libc::{self, c_int};
+use nix::sys::signal::{sigaction, SigAction, SigHandler, SaFlags, SigSet};
+
+use {check_catchable, Error, Signal};
+
+
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+static SECONDS: AtomicU64 = AtomicU64::new(0);
+static NANOSECONDS: AtomicU32 = AtomicU32::new(0);
+
+/// Record the signal as the last one received
+///
+/// This function is async-signal-safe, it only reads the clock and stores
+/// into atomic variables (`errno` is not changed either).
+pub fn record(sig: c_int) {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) };
+    // zero signal marks the record as being updated
+    SIGNAL.store(0, Ordering::SeqCst);
+    SECONDS.store(now.tv_sec as u64, Ordering::SeqCst);
+    NANOSECONDS.store(now.tv_nsec as u32, Ordering::SeqCst);
+    SIGNAL.store(sig, Ordering::SeqCst);
+}
+
+/// Returns the last recorded signal and the time it was received
+///
+/// Returns `None` if no signal was recorded yet (or if a record is being
+/// written right now by a handler in another thread).
+pub fn last_signal() -> Option<(c_int, SystemTime)> {
+    let sig = SIGNAL.load(Ordering::SeqCst);
+    if sig == 0 {
+        return None;
+    }
+    let time = UNIX_EPOCH + Duration::new(
+        SECONDS.load(Ordering::SeqCst),
+        NANOSECONDS.load(Ordering::SeqCst));
+    Some((sig, time))
+}
+
+extern "C" fn record_handler(sig: c_int) {
+    record(sig);
+    // The disposition is reset to default by SA_RESETHAND, and the signal
+    // is blocked while in the handler, so it's delivered with the default
+    // action as soon as the handler returns
+    unsafe { libc::raise(sig) };
+}
+
+/// Install a handler that records the signals and lets their default action
+/// happen
+///
+/// The handler is one-shot: it's reset to the default disposition when the
+/// signal is received for the first time. It replaces any handler set for
+/// the same signals before (including the `exec_handler` one, which records
+/// the signal itself).
+///
+/// Returns `Error::Uncatchable` without installing anything if the list
+/// contains signals that can't be caught.
+pub fn install(signals: &[Signal]) -> Result<(), Error> {
+    check_catchable(signals)?;
+    for &sig in signals {
+        unsafe {
+            sigaction(sig.into(), &SigAction::new(
+                SigHandler::Handler(record_handler),
+                SaFlags::SA_RESETHAND, SigSet::empty()))
+                .map_err(|e| Error::sigaction(sig, e))?;
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::mem::zeroed;
+    use std::ptr::null;
+
+    use libc;
+    use nix::sys::signal::{raise, SigSet};
+
+    use Signal::SIGURG;
+    use super::{install, last_signal};
+
+    #[test]
+    fn install_records_and_resets() {
+        // default action of SIGURG is to ignore it
+        install(&[SIGURG]).unwrap();
+        let mut urg = SigSet::empty();
+        urg.add(SIGURG.into());
+        urg.thread_unblock().unwrap();
+        raise(SIGURG.into()).unwrap();
+        let (sig, _) = last_signal().unwrap();
+        assert_eq!(sig, libc::SIGURG);
+        let mut current: libc::sigaction = unsafe { zeroed() };
+        unsafe { libc::sigaction(libc::SIGURG, null(), &mut current) };
+        assert_eq!(current.sa_sigaction, libc::SIG_DFL);
+    }
+}
@@ -21,21 +21,181 @@
 //! Note, masking out signals may also be achieved by trap (just don't call
 //! either `wait()` or `next()`)
 //!
-//! On TODO list:
+//! On linux, the `signalfd` module allows receiving signals via a file
+//! descriptor to integrate signal handling into an existing event loop.
 //!
-//! * `signalfd`
+//! With the `serde` feature, `Signal` and `SignalSet` can be read from config
+//! files, by name (`"SIGTERM"`, `"TERM"`) or by number. With the `clap`
+//! feature, `Signal` can be used as a command-line argument. With the
+//! `tokio` feature, signals can be awaited in a tokio runtime, see the
+//! `tokio` module. With the `mio` feature, `SignalFd` and a `Trap` created
+//! by `trap_via_signalfd()` can be registered in a `mio::Poll`, and with
+//! the `calloop` feature `SignalFd` is a `calloop::EventSource`.
+//!
+//! On Windows, only the `console` module is available, which handles console
+//! control events (e.g. Ctrl+C) in the same style as `Trap`.
 //!
 //! The library tested only on linux
 //!
 #![warn(missing_docs)]
 #![warn(missing_debug_implementations)]
 
+#[cfg(unix)]
 extern crate libc;
+#[cfg(unix)]
 extern crate nix;
+#[cfg(feature = "futures")]
+extern crate futures_core;
+#[cfg(windows)]
+extern crate windows_sys;
+#[cfg(all(unix, feature = "serde"))]
+extern crate serde;
+#[cfg(all(unix, feature = "clap"))]
+extern crate clap;
+// renamed, as the integration module has the same name
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+extern crate tokio as tokio_crate;
+#[cfg(all(target_os = "linux", feature = "mio"))]
+extern crate mio;
+#[cfg(all(target_os = "linux", feature = "calloop"))]
+extern crate calloop;
 
+#[cfg(unix)]
 mod ffi;
+#[cfg(unix)]
+mod signal;
+#[cfg(unix)]
+mod signal_set;
+#[cfg(all(unix, feature = "serde"))]
+mod serialize;
+#[cfg(all(unix, feature = "clap"))]
+pub mod value_parser;
+#[cfg(unix)]
+pub mod error;
+pub mod backoff;
+#[cfg(unix)]
 pub mod exec_handler;
+#[cfg(unix)]
 pub mod trap;
+#[cfg(unix)]
+pub mod siginfo;
+#[cfg(unix)]
+pub mod children;
+#[cfg(unix)]
+pub mod reaper;
+#[cfg(unix)]
+pub mod fork;
+#[cfg(unix)]
+pub mod groups;
+#[cfg(unix)]
+pub mod events;
+#[cfg(unix)]
+pub mod proxy;
+#[cfg(unix)]
+pub mod init;
+#[cfg(unix)]
+pub mod last_signal;
+#[cfg(unix)]
+pub mod crash_report;
+#[cfg(unix)]
+mod sigwait_thread;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod realtime;
+#[cfg(any(target_os = "macos", target_os = "ios",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+mod kqueue;
+#[cfg(target_os = "linux")]
+pub mod select;
+#[cfg(target_os = "linux")]
+pub mod logger;
+#[cfg(target_os = "linux")]
+pub mod supervisor;
+#[cfg(target_os = "linux")]
+pub mod child_watcher;
+#[cfg(target_os = "linux")]
+pub mod pidfd;
+#[cfg(target_os = "linux")]
+pub mod subreaper;
+#[cfg(target_os = "linux")]
+pub mod signalfd;
+#[cfg(target_os = "linux")]
+pub mod async_trap;
+#[cfg(all(target_os = "linux", feature = "futures"))]
+pub mod stream;
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+pub mod tokio;
+#[cfg(windows)]
+pub mod console;
+
+#[cfg(unix)]
+pub use error::Error;
+#[cfg(unix)]
+pub use signal::{Signal, ParseSignalError};
+#[cfg(unix)]
+pub use signal_set::SignalSet;
+/// Set of signals (reexported from nix)
+#[cfg(unix)]
+pub use nix::sys::signal::SigSet;
+
+/// Build a `SigSet` from signal names
+///
+/// E.g. `sigset!(SIGTERM, SIGINT, SIGHUP)`. Names are checked at compile
+/// time, as they are resolved as variants of `Signal`. The resulting set
+/// can be passed to `Trap::from_sigset` or to any function accepting a
+/// `SigSet` (e.g. `pthread_sigmask`).
+#[cfg(unix)]
+#[macro_export]
+macro_rules! sigset {
+    ($($sig:ident),* $(,)*) => {{
+        #[allow(unused_mut)]
+        let mut set = $crate::SigSet::empty();
+        $( set.add($crate::Signal::$sig.into()); )*
+        set
+    }};
+}
+
+/// Convert the signal into the raw signal number
+///
+/// This is the canonical conversion to use when calling `libc` functions
+/// or printing signal numbers, instead of `sig as c_int` casts.
+#[cfg(unix)]
+pub fn to_raw(sig: Signal) -> libc::c_int {
+    sig as libc::c_int
+}
+
+/// Convert the raw signal number into the `Signal`
+///
+/// Returns `None` if the number isn't a valid signal or isn't representable
+/// by `Signal` (e.g. a realtime signal).
+#[cfg(unix)]
+pub fn from_raw(raw: libc::c_int) -> Option<Signal> {
+    Signal::from_c_int(raw).ok()
+}
+
+#[cfg(unix)]
+const UNCATCHABLE_SIGNALS: &[Signal] = &[Signal::SIGKILL, Signal::SIGSTOP];
+
+/// Returns signals that can't be caught, blocked or ignored
+///
+/// These are `SIGKILL` and `SIGSTOP`. Functions of this crate that accept a
+/// list of signals reject them up front.
+#[cfg(unix)]
+pub fn uncatchable_signals() -> &'static [Signal] {
+    UNCATCHABLE_SIGNALS
+}
+
+/// Returns `true` if the signal can be caught, blocked and ignored
+#[cfg(unix)]
+pub fn is_catchable(sig: Signal) -> bool {
+    !UNCATCHABLE_SIGNALS.contains(&sig)
+}
 
-/// Signal number (reexported from nix)
-pub use nix::sys::signal::Signal;
+/// Returns an error if any of the signals can't be caught
+#[cfg(unix)]
+fn check_catchable(signals: &[Signal]) -> Result<(), Error> {
+    match signals.iter().find(|&&sig| !is_catchable(sig)) {
+        Some(&sig) => Err(Error::Uncatchable(sig)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,105 @@
+//! Logging of received signals for debugging
+//!
+//! `SignalLogger` traps signals and writes a line for every signal received,
+//! containing signal name, the sender and the reason decoded from `si_code`,
+//! e.g. `SIGTERM from pid 1234 uid 1000: sent by kill`.
+//!
+//! This is what you want when debugging "who is killing my process?". Note
+//! that the signals are trapped, i.e. their default action doesn't happen
+//! while the logger exists. So it's up to the caller to act on them (e.g.
+//! exit on `SIGTERM`).
+//!
+//! Only available on linux.
+
+use std::fmt;
+use std::io::{self, Write, Stderr, stderr};
+
+use {Error, Signal};
+
+use exec_handler::fatal_signals;
+use is_catchable;
+use siginfo::SigInfo;
+use trap::Trap;
+
+
+/// Traps signals and logs every received one
+pub struct SignalLogger<W: Write> {
+    trap: Trap,
+    writer: W,
+}
+
+impl SignalLogger<Stderr> {
+    /// Log specified signals to stderr
+    pub fn new(signals: &[Signal]) -> Result<SignalLogger<Stderr>, Error> {
+        SignalLogger::with_writer(signals, stderr())
+    }
+
+    /// Log every signal that can be trapped to stderr
+    ///
+    /// This includes all catchable signals except those raised for faults
+    /// of the program itself (see `exec_handler::fatal_signals()`), which
+    /// can't be usefully waited for.
+    pub fn catch_all() -> Result<SignalLogger<Stderr>, Error> {
+        let signals = Signal::iterator()
+            .filter(|&sig| is_catchable(sig))
+            .filter(|sig| !fatal_signals().contains(sig))
+            .collect::<Vec<_>>();
+        SignalLogger::new(&signals)
+    }
+}
+
+impl<W: Write> SignalLogger<W> {
+    /// Log specified signals to the writer
+    ///
+    /// Fails the same way as `Trap::trap()`.
+    pub fn with_writer(signals: &[Signal], writer: W)
+        -> Result<SignalLogger<W>, Error>
+    {
+        Ok(SignalLogger {
+            trap: Trap::trap(signals)?,
+            writer,
+        })
+    }
+
+    /// Wait for the next signal and log it
+    ///
+    /// Returns the information about the signal, so the caller can act on
+    /// it after it's logged.
+    pub fn log_next(&mut self) -> io::Result<SigInfo> {
+        let info = self.trap.next_info()?;
+        writeln!(self.writer, "{}", Line(&info))?;
+        Ok(info)
+    }
+
+    /// Returns the writer
+    pub fn writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
+}
+
+impl<W: Write> fmt::Debug for SignalLogger<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignalLogger")
+            .field("trap", &self.trap)
+            .finish()
+    }
+}
+
+struct Line<'a>(&'a SigInfo);
+
+impl<'a> fmt::Display for Line<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let info = self.0;
+        match info.signal() {
+            Some(sig) => write!(f, "{}", sig)?,
+            None => write!(f, "signal {}", info.raw_signal())?,
+        }
+        if let Some(pid) = info.sender_pid() {
+            write!(f, " from pid {}", pid)?;
+        }
+        if let Some(uid) = info.sender_uid() {
+            write!(f, " uid {}", uid)?;
+        }
+        write!(f, ": {}", info.code())
+    }
+}
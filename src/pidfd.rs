@@ -0,0 +1,188 @@
+//! Process file descriptors (`pidfd_open`, `pidfd_send_signal`)
+//!
+//! A `PidFd` refers to a specific process rather than to a pid, so a signal
+//! sent with `send_signal()` can't hit an unrelated process that got the pid
+//! after the original one exited and was reaped. The descriptor becomes
+//! readable when the process exits, so it can be polled together with other
+//! descriptors instead of waiting for `SIGCHLD`.
+//!
+//! Only available on linux 5.3 and later, older kernels return
+//! `PidfdError::Unsupported`.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr::null_mut;
+use std::time::Instant;
+
+use libc::{self, c_int, c_uint};
+use nix;
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags};
+use nix::unistd::{self, Pid};
+
+use child_watcher::{ChildInfo, ChildWatcher};
+use ffi::wait_readable;
+use Signal;
+
+
+/// Error returned by the `pidfd` functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PidfdError {
+    /// The kernel doesn't support pidfds (`ENOSYS`)
+    Unsupported,
+    /// The process doesn't exist or has exited (`ESRCH`)
+    NoProcess,
+    /// Not allowed to signal the process (`EPERM`)
+    PermissionDenied,
+    /// Any other error of the system call
+    Os(Errno),
+}
+
+impl PidfdError {
+    /// Returns the `errno` value of the error
+    pub fn errno(&self) -> Errno {
+        match *self {
+            PidfdError::Unsupported => Errno::ENOSYS,
+            PidfdError::NoProcess => Errno::ESRCH,
+            PidfdError::PermissionDenied => Errno::EPERM,
+            PidfdError::Os(errno) => errno,
+        }
+    }
+
+    fn last() -> PidfdError {
+        match Errno::last() {
+            Errno::ENOSYS => PidfdError::Unsupported,
+            Errno::ESRCH => PidfdError::NoProcess,
+            Errno::EPERM => PidfdError::PermissionDenied,
+            errno => PidfdError::Os(errno),
+        }
+    }
+}
+
+impl fmt::Display for PidfdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PidfdError::Unsupported => {
+                f.write_str("pidfd is not supported by the kernel")
+            }
+            PidfdError::NoProcess => f.write_str("no such process"),
+            PidfdError::PermissionDenied => {
+                f.write_str("not permitted to signal the process")
+            }
+            PidfdError::Os(errno) => {
+                write!(f, "pidfd error: {}", errno.desc())
+            }
+        }
+    }
+}
+
+impl error::Error for PidfdError {}
+
+impl From<PidfdError> for io::Error {
+    fn from(e: PidfdError) -> io::Error {
+        io::Error::from_raw_os_error(e.errno() as i32)
+    }
+}
+
+/// A file descriptor referring to a process
+///
+/// The descriptor is closed on drop. It's opened with `O_CLOEXEC`.
+#[derive(Debug)]
+pub struct PidFd {
+    fd: RawFd,
+    pid: Pid,
+}
+
+impl PidFd {
+    /// Open a descriptor for the process
+    ///
+    /// To be race-free for a child, open it before the child is reaped: the
+    /// pid of a zombie can't be reused.
+    pub fn open(pid: Pid) -> Result<PidFd, PidfdError> {
+        let fd = unsafe {
+            libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0 as c_uint)
+        };
+        if fd < 0 {
+            return Err(PidfdError::last());
+        }
+        Ok(PidFd { fd: fd as RawFd, pid })
+    }
+
+    /// Returns the pid of the process
+    ///
+    /// The pid may be reused by another process once this one is reaped.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Send the signal to the process
+    ///
+    /// Returns `PidfdError::NoProcess` if the process has been reaped. The
+    /// signal can never reach a different process that reused the pid.
+    pub fn send_signal(&self, signal: Signal) -> Result<(), PidfdError> {
+        self.send_raw(signal as c_int)
+    }
+
+    /// Send the signal by its number, e.g. a realtime signal
+    pub fn send_raw(&self, signal: c_int) -> Result<(), PidfdError> {
+        let res = unsafe {
+            libc::syscall(libc::SYS_pidfd_send_signal, self.fd, signal,
+                null_mut::<libc::siginfo_t>(), 0 as c_uint)
+        };
+        if res < 0 {
+            return Err(PidfdError::last());
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the process has exited
+    ///
+    /// The process may still need to be reaped.
+    pub fn has_exited(&self) -> nix::Result<bool> {
+        loop {
+            match wait_readable(self.fd, Some(Instant::now())) {
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                res => return res,
+            }
+        }
+    }
+
+    /// Wait until the process exits or deadline is reached
+    ///
+    /// Returns `false` on deadline. The process isn't reaped.
+    pub fn wait_exit(&self, deadline: Instant) -> nix::Result<bool> {
+        loop {
+            match wait_readable(self.fd, Some(deadline)) {
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                res => return res,
+            }
+        }
+    }
+
+    /// Reap the process if it has exited
+    ///
+    /// The process must be a child of the calling process. Returns `None`
+    /// if it's still running, never blocks. Requires linux 5.4.
+    pub fn reap(&self) -> nix::Result<Option<ChildInfo>> {
+        ChildWatcher::pidfd(self.fd).reap()
+    }
+
+    /// Returns a `PollFd` to wait for the exit with `nix::poll::poll`
+    pub fn poll_fd(&self) -> PollFd {
+        PollFd::new(self.fd, PollFlags::POLLIN)
+    }
+}
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
@@ -0,0 +1,203 @@
+//! Forwarding signals to a child, like `tini` or `dumb-init`
+//!
+//! A `Proxy` spawns a command, passes the trapped signals on to it and
+//! returns when the child exits. The exit status of the child can then be
+//! mirrored as the exit of the proxy process with `exit_with()`, so the
+//! proxy is transparent for whoever started it. This is what a container
+//! entrypoint shim does: the orchestrator sends `SIGTERM` to the shim and
+//! expects the real program to receive it.
+//!
+//! By default `groups::FORWARDED_SIGNALS` are forwarded to the child only.
+//! With `process_group(true)` the child is started in its own process group
+//! and the signals are sent to the whole group, so helpers spawned by a
+//! shell script receive them too. Only the child is reaped unless
+//! `reap_orphans(true)` is set, which is required when the proxy runs as
+//! PID 1, see the `init` module.
+
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command};
+
+use libc::pid_t;
+use nix;
+use nix::errno::Errno;
+use nix::sys::signal::{kill, pthread_sigmask, SigmaskHow, SigSet};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+use children::{reap_any, ChildEvent, Children};
+use ffi::io_error;
+use groups::FORWARDED_SIGNALS;
+use trap::Trap;
+use {to_raw, Error, Signal};
+
+
+/// Spawns a command and forwards signals to it, see the module docs
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    signals: Vec<Signal>,
+    group: bool,
+    reap_orphans: bool,
+}
+
+impl Proxy {
+    /// Forward `groups::FORWARDED_SIGNALS` to the child
+    pub fn new() -> Proxy {
+        Proxy {
+            signals: FORWARDED_SIGNALS.to_vec(),
+            group: false,
+            reap_orphans: false,
+        }
+    }
+
+    /// Forward these signals instead of the default ones
+    ///
+    /// `SIGCHLD` is always trapped to notice the exit of the child, and it
+    /// isn't forwarded even if listed.
+    pub fn signals(mut self, signals: &[Signal]) -> Proxy {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// Start the child in a new process group and signal the whole group
+    ///
+    /// The child is not in the foreground process group of the terminal
+    /// anymore, so this is meant for the proxies without a terminal (e.g.
+    /// in a container).
+    pub fn process_group(mut self, value: bool) -> Proxy {
+        self.group = value;
+        self
+    }
+
+    /// Reap every child of the process, not only the spawned one
+    ///
+    /// Orphaned processes are reparented to PID 1 (or to the nearest
+    /// subreaper), which has to reap them or they stay zombies forever.
+    /// Don't enable it if other parts of the program wait for their own
+    /// children, their exit statuses are consumed here.
+    pub fn reap_orphans(mut self, value: bool) -> Proxy {
+        self.reap_orphans = value;
+        self
+    }
+
+    /// Spawn the command and forward the signals until it exits
+    ///
+    /// The signals are trapped before the command is spawned, so none of
+    /// them is lost or runs its default action on the proxy. They are
+    /// unblocked in the child before `exec`, as the signal mask is
+    /// inherited (and dispositions are reset by `exec`). Returns the
+    /// status of the child, either `WaitStatus::Exited` or
+    /// `WaitStatus::Signaled`. Other children of the process are only
+    /// reaped with `reap_orphans(true)`.
+    ///
+    /// As with any `Trap`, the signals should be blocked in the other
+    /// threads of the process.
+    pub fn run(&self, command: &mut Command) -> io::Result<WaitStatus> {
+        let mut trap = self.trap()?;
+        if self.group {
+            command.process_group(0);
+        }
+        let trapped = self.sigset();
+        unsafe {
+            command.pre_exec(move || {
+                pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(&trapped), None)
+                    .map_err(io_error)
+            });
+        }
+        let child = command.spawn()?;
+        let pid = Pid::from_raw(child.id() as pid_t);
+        self.forward(&mut trap, pid).map_err(io_error)
+    }
+
+    /// Forward the signals to an already running child until it exits
+    ///
+    /// Same as `run()`, but the signals that arrived before this call
+    /// aren't forwarded. With `process_group(true)` the child must be the
+    /// leader of its process group.
+    pub fn attach(&self, child: Pid) -> nix::Result<WaitStatus> {
+        let mut trap = self.trap()?;
+        self.forward(&mut trap, child)
+    }
+
+    fn trap(&self) -> Result<Trap, Error> {
+        Trap::trap_checked(&self.trapped())
+    }
+
+    fn trapped(&self) -> Vec<Signal> {
+        let mut signals = self.signals.clone();
+        if !signals.contains(&Signal::SIGCHLD) {
+            signals.push(Signal::SIGCHLD);
+        }
+        signals
+    }
+
+    fn sigset(&self) -> SigSet {
+        let mut sigset = SigSet::empty();
+        for sig in self.trapped() {
+            sigset.add(sig.into());
+        }
+        sigset
+    }
+
+    fn forward(&self, trap: &mut Trap, child: Pid)
+        -> nix::Result<WaitStatus>
+    {
+        let mut children = Children::new();
+        children.watch_child(child);
+        let target = if self.group {
+            Pid::from_raw(-child.as_raw())
+        } else {
+            child
+        };
+        loop {
+            // the child may have exited before `SIGCHLD` was trapped
+            if self.reap_orphans {
+                let exited = reap_any().into_iter()
+                    .find(|event| event.pid == child);
+                if let Some(ChildEvent { status, .. }) = exited {
+                    return Ok(status);
+                }
+            }
+            // also finds out whether the child was reaped by someone else
+            if let Some(status) = children.reap()?.pop() {
+                return Ok(status);
+            }
+            if children.is_empty() {
+                // reaped by someone else, the status is lost
+                return Err(nix::Error::Sys(Errno::ECHILD));
+            }
+            match trap.next() {
+                Some(Signal::SIGCHLD) | None => {}
+                // the child may be dead but not reaped yet
+                Some(sig) => { kill(target, Some(sig.into())).ok(); }
+            }
+        }
+    }
+}
+
+impl Default for Proxy {
+    fn default() -> Proxy {
+        Proxy::new()
+    }
+}
+
+/// Returns the exit code mirroring the status of the child
+///
+/// This is the code itself for an exited child and `128 + signal` for a
+/// child killed by a signal, the same as reported by the shell. Any other
+/// status (i.e. stopped or continued) maps to `1`.
+pub fn exit_code(status: WaitStatus) -> i32 {
+    match status {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, sig, _) => 128 + to_raw(sig.into()),
+        _ => 1,
+    }
+}
+
+/// Exit the process with the code mirroring the status of the child
+///
+/// See `exit_code()`. The proxy exits normally rather than killing itself
+/// with the same signal, so it never dumps core for the child.
+pub fn exit_with(status: WaitStatus) -> ! {
+    process::exit(exit_code(status))
+}
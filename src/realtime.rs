@@ -0,0 +1,245 @@
+//! Realtime signals
+//!
+//! Realtime signals are numbered from `SIGRTMIN` to `SIGRTMAX`, but these
+//! are not constants: the C library reserves a few of the lowest ones for
+//! its own use (e.g. glibc uses two for threading) and reports the rest
+//! at runtime. So realtime signals should be allocated by a logical index
+//! starting from `SIGRTMIN` rather than by hardcoded numbers.
+//!
+//! Realtime signals aren't representable by `Signal`, so raw signal numbers
+//! are used here. `RawTrap` is a trap receiving signals by their numbers.
+//!
+//! Only available on linux and android.
+
+use std::fmt;
+use std::mem;
+use std::ptr::null_mut;
+use std::time::Instant;
+
+use libc::{self, c_int, pid_t};
+use nix;
+use nix::errno::Errno;
+
+use siginfo::SigInfo;
+use {from_raw, is_catchable, Error};
+
+
+/// Returns the range of realtime signals usable by the application
+///
+/// Both bounds are inclusive. Signals reserved by the C library are already
+/// excluded from the range.
+pub fn realtime_signal_range() -> (c_int, c_int) {
+    (libc::SIGRTMIN(), libc::SIGRTMAX())
+}
+
+/// Returns the realtime signal number for a logical index
+///
+/// Index zero is `SIGRTMIN`. Returns `None` if there are not enough
+/// realtime signals on this platform.
+pub fn realtime_signal(index: usize) -> Option<c_int> {
+    let (min, max) = realtime_signal_range();
+    if index > (max - min) as usize {
+        return None;
+    }
+    Some(min + index as c_int)
+}
+
+/// Send a signal with a value to the process
+///
+/// This is `sigqueue()`: unlike standard signals, multiple instances of a
+/// realtime signal are queued rather than coalesced, and each one carries
+/// the `value`, which the receiver gets by `SigInfo::value()` (e.g. from
+/// `RawTrap::wait_info()` or `Trap::wait_info()`). Standard signals may be
+/// sent too, but they are still coalesced.
+pub fn send_queued(pid: pid_t, sig: c_int, value: usize) -> nix::Result<()> {
+    let value = libc::sigval { sival_ptr: value as *mut libc::c_void };
+    if unsafe { libc::sigqueue(pid, sig, value) } != 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(())
+}
+
+/// A trap for signals specified by raw numbers
+///
+/// This is the same as `Trap` (signals are masked out and can be waited
+/// for, handlers and mask are restored on drop), but accepts and returns
+/// raw signal numbers, so it works for realtime signals too. Standard
+/// signals may be trapped by it as well.
+///
+/// Like `Trap`, it must be dropped in the thread where it was created.
+pub struct RawTrap {
+    signals: Vec<c_int>,
+    sigset: libc::sigset_t,
+    /// Signals that were not blocked before the trap
+    blocked: libc::sigset_t,
+    oldactions: Vec<(c_int, libc::sigaction)>,
+}
+
+/// The first realtime signal of the kernel, some of them are reserved by
+/// the C library
+const KERNEL_SIGRTMIN: c_int = 32;
+
+extern "C" fn empty_handler(_: c_int) {}
+
+impl RawTrap {
+    /// Mask out the signals and install empty handlers for them
+    ///
+    /// Returns `Error::InvalidSignal` for numbers that are not valid
+    /// signals (including the realtime signals reserved by the C library,
+    /// below `SIGRTMIN`) and `Error::Uncatchable` for `SIGKILL` and
+    /// `SIGSTOP`, in these cases nothing is changed. Duplicate signals are
+    /// trapped once.
+    pub fn new(signals: &[c_int]) -> Result<RawTrap, Error> {
+        for &sig in signals {
+            if sig <= 0 || sig > libc::SIGRTMAX() ||
+                (KERNEL_SIGRTMIN..libc::SIGRTMIN()).contains(&sig)
+            {
+                return Err(Error::InvalidSignal(sig));
+            }
+            match from_raw(sig) {
+                Some(signal) if !is_catchable(signal) => {
+                    return Err(Error::Uncatchable(signal));
+                }
+                _ => {}
+            }
+        }
+        // a second `sigaction` would save the empty handler as the old one
+        let mut unique = Vec::with_capacity(signals.len());
+        for &sig in signals {
+            if !unique.contains(&sig) {
+                unique.push(sig);
+            }
+        }
+        let signals = &unique[..];
+        unsafe {
+            let mut sigset = mem::zeroed();
+            libc::sigemptyset(&mut sigset);
+            for &sig in signals {
+                libc::sigaddset(&mut sigset, sig);
+            }
+            let mut oldset = mem::zeroed();
+            let res = libc::pthread_sigmask(libc::SIG_BLOCK,
+                                            &sigset, &mut oldset);
+            if res != 0 {
+                return Err(Error::Sigmask(Errno::from_i32(res)));
+            }
+            let mut blocked = mem::zeroed();
+            libc::sigemptyset(&mut blocked);
+            for &sig in signals {
+                if libc::sigismember(&oldset, sig) == 0 {
+                    libc::sigaddset(&mut blocked, sig);
+                }
+            }
+            // From now on dropping the trap restores everything
+            let mut trap = RawTrap {
+                signals: signals.to_vec(),
+                sigset,
+                blocked,
+                oldactions: Vec::new(),
+            };
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = empty_handler as extern "C" fn(c_int)
+                as libc::sighandler_t;
+            action.sa_mask = sigset;
+            for &sig in signals {
+                let mut old = mem::zeroed();
+                if libc::sigaction(sig, &action, &mut old) != 0 {
+                    return Err(Error::Sigaction(sig, Errno::last()));
+                }
+                trap.oldactions.push((sig, old));
+            }
+            Ok(trap)
+        }
+    }
+
+    /// Returns the signals of the trap
+    pub fn signals(&self) -> &[c_int] {
+        &self.signals
+    }
+
+    /// Wait until any of signals arrived or deadline is reached
+    ///
+    /// Returns the raw signal number, or `None` on deadline.
+    pub fn wait(&self, deadline: Instant) -> Result<Option<c_int>, Error> {
+        Ok(self.wait_info(deadline)?.map(|info| info.raw_signal()))
+    }
+
+    /// Block until any of signals arrived
+    ///
+    /// Returns the raw signal number. `Ok(None)` means the trap has no
+    /// signals, so the iteration is over.
+    pub fn try_next(&mut self) -> Result<Option<c_int>, Error> {
+        if self.signals.is_empty() {
+            return Ok(None);
+        }
+        loop {
+            let mut info = unsafe { mem::zeroed() };
+            let sig = unsafe { libc::sigwaitinfo(&self.sigset, &mut info) };
+            if sig > 0 {
+                return Ok(Some(sig));
+            }
+            match Errno::last() {
+                Errno::EINTR => continue,
+                err => return Err(Error::Wait(err)),
+            }
+        }
+    }
+
+    /// Same as `wait()` but returns full signal information
+    pub fn wait_info(&self, deadline: Instant)
+        -> Result<Option<SigInfo>, Error>
+    {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let tm = libc::timespec {
+                tv_sec: timeout.as_secs() as libc::time_t,
+                tv_nsec: timeout.subsec_nanos() as libc::c_long,
+            };
+            let mut info = unsafe { mem::zeroed() };
+            let sig = unsafe {
+                libc::sigtimedwait(&self.sigset, &mut info, &tm)
+            };
+            if sig > 0 {
+                return Ok(Some(SigInfo::from(&info)));
+            }
+            match Errno::last() {
+                Errno::EAGAIN => return Ok(None),
+                Errno::EINTR => continue,
+                err => return Err(Error::Wait(err)),
+            }
+        }
+    }
+}
+
+/// Yields raw numbers of trapped signals as they arrive
+///
+/// Panics if waiting fails, use `try_next()` to get the error instead.
+impl Iterator for RawTrap {
+    type Item = c_int;
+    fn next(&mut self) -> Option<c_int> {
+        match self.try_next() {
+            Ok(sig) => sig,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+impl Drop for RawTrap {
+    fn drop(&mut self) {
+        unsafe {
+            for &(sig, ref old) in &self.oldactions {
+                libc::sigaction(sig, old, null_mut());
+            }
+            libc::pthread_sigmask(libc::SIG_UNBLOCK, &self.blocked,
+                                  null_mut());
+        }
+    }
+}
+
+impl fmt::Debug for RawTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawTrap")
+            .field("signals", &self.signals)
+            .finish()
+    }
+}
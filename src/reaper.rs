@@ -0,0 +1,209 @@
+//! Child reaper shared by the components of a process
+//!
+//! Each component registers the children it has spawned with `watch()` (or
+//! `watch_with()` for a callback) and receives a `ChildEvent` when the child
+//! exits. A component that needs to know about every child uses
+//! `watch_any()` instead. The `Reaper` is cheap to clone, all the clones
+//! share the registrations.
+//!
+//! Nothing happens on its own: the main loop of the process calls `reap()`
+//! every time `SIGCHLD` is received (e.g. from a `Trap`), and the reaper
+//! reaps the children and dispatches the notifications.
+//!
+//! As long as there are no `watch_any()` subscribers, only the registered
+//! children are reaped (like `children::Children` does), so the exit status
+//! of other children isn't stolen. With a subscriber for any child, all the
+//! children are reaped with `children::reap_any()`. Note that in this mode a
+//! child may exit and be reaped before its pid is registered, in which case
+//! only the `watch_any()` subscribers are notified.
+//!
+//! On linux, `adopt_orphans()` makes the process a child subreaper (see the
+//! `subreaper` module), so the orphaned descendants of the children are
+//! reparented to it instead of PID 1. From then on all the children are
+//! reaped, and the adopted ones are reported to `watch_any()` subscribers.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use nix;
+use nix::unistd::Pid;
+
+use children::{reap_any, ChildEvent, Children};
+#[cfg(target_os = "linux")]
+use subreaper::set_child_subreaper;
+
+
+#[derive(Clone)]
+enum Subscriber {
+    Channel(Sender<ChildEvent>),
+    Callback(Arc<dyn Fn(ChildEvent) + Send + Sync>),
+}
+
+#[derive(Default)]
+struct Registry {
+    pids: HashMap<Pid, Vec<Subscriber>>,
+    // numbered, to remove the closed channels after dispatching
+    any: Vec<(u64, Subscriber)>,
+    next_id: u64,
+    /// Reap all the children, even without `any` subscribers
+    orphans: bool,
+}
+
+/// A registry of children and of the parties interested in their exit
+#[derive(Clone, Default)]
+pub struct Reaper {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Reaper {
+    /// Create a reaper with no registrations
+    pub fn new() -> Reaper {
+        Reaper::default()
+    }
+
+    /// Send a `ChildEvent` to the channel when the child exits
+    ///
+    /// The same pid may be watched several times, every subscriber is
+    /// notified once.
+    pub fn watch(&self, pid: Pid, sender: Sender<ChildEvent>) {
+        self.subscribe(Some(pid), Subscriber::Channel(sender));
+    }
+
+    /// Call the function with a `ChildEvent` when the child exits
+    ///
+    /// The function is called by the thread calling `reap()`, without
+    /// holding any locks, so it may register more children.
+    pub fn watch_with<F>(&self, pid: Pid, func: F)
+        where F: Fn(ChildEvent) + Send + Sync + 'static
+    {
+        self.subscribe(Some(pid), Subscriber::Callback(Arc::new(func)));
+    }
+
+    /// Send a `ChildEvent` to the channel for every child that exits
+    ///
+    /// This switches the reaper to reaping all the children, see the module
+    /// docs. The subscription ends when the receiver is dropped.
+    pub fn watch_any(&self, sender: Sender<ChildEvent>) {
+        self.subscribe(None, Subscriber::Channel(sender));
+    }
+
+    /// Call the function with a `ChildEvent` for every child that exits
+    ///
+    /// Same as `watch_any()`, but the subscription is never removed.
+    pub fn watch_any_with<F>(&self, func: F)
+        where F: Fn(ChildEvent) + Send + Sync + 'static
+    {
+        self.subscribe(None, Subscriber::Callback(Arc::new(func)));
+    }
+
+    /// Become a child subreaper and reap the adopted orphans
+    ///
+    /// This marks the whole process, not just this reaper, and is not
+    /// undone when the reaper is dropped.
+    #[cfg(target_os = "linux")]
+    pub fn adopt_orphans(&self) -> nix::Result<()> {
+        set_child_subreaper(true)?;
+        self.lock().orphans = true;
+        Ok(())
+    }
+
+    /// Remove all the subscribers of the child
+    ///
+    /// Returns `false` if the child wasn't watched. The child isn't reaped
+    /// anymore (unless there are `watch_any()` subscribers).
+    pub fn unwatch(&self, pid: Pid) -> bool {
+        self.lock().pids.remove(&pid).is_some()
+    }
+
+    /// Returns `true` if the child is watched
+    pub fn is_watched(&self, pid: Pid) -> bool {
+        self.lock().pids.contains_key(&pid)
+    }
+
+    /// Reap the exited children and notify the subscribers
+    ///
+    /// Call it every time `SIGCHLD` is received. Returns the number of
+    /// children reaped. Children reaped by someone else (`ECHILD`) are
+    /// removed silently.
+    pub fn reap(&self) -> nix::Result<usize> {
+        let (events, notices) = {
+            let mut registry = self.lock();
+            let events = if registry.any.is_empty() && !registry.orphans {
+                reap_watched(&mut registry)?
+            } else {
+                reap_any()
+            };
+            let notices = events.iter().map(|&event| {
+                let mut subscribers = registry.pids.remove(&event.pid)
+                    .unwrap_or_default().into_iter()
+                    .map(|sub| (None, sub))
+                    .collect::<Vec<_>>();
+                subscribers.extend(registry.any.iter()
+                    .map(|(id, sub)| (Some(*id), sub.clone())));
+                (event, subscribers)
+            }).collect::<Vec<_>>();
+            (events.len(), notices)
+        };
+        let mut closed = Vec::new();
+        for (event, subscribers) in notices {
+            for (id, sub) in subscribers {
+                match sub {
+                    Subscriber::Channel(sender) => {
+                        if sender.send(event).is_err() {
+                            closed.extend(id);
+                        }
+                    }
+                    Subscriber::Callback(func) => func(event),
+                }
+            }
+        }
+        if !closed.is_empty() {
+            self.lock().any.retain(|(id, _)| !closed.contains(id));
+        }
+        Ok(events)
+    }
+
+    fn subscribe(&self, pid: Option<Pid>, sub: Subscriber) {
+        let mut registry = self.lock();
+        match pid {
+            Some(pid) => registry.pids.entry(pid).or_default().push(sub),
+            None => {
+                let id = registry.next_id;
+                registry.next_id += 1;
+                registry.any.push((id, sub));
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn reap_watched(registry: &mut Registry) -> nix::Result<Vec<ChildEvent>> {
+    let mut children = Children::new();
+    for &pid in registry.pids.keys() {
+        children.watch_child(pid);
+    }
+    let events = children.reap()?.into_iter()
+        .filter_map(|status| Some(ChildEvent { pid: status.pid()?, status }))
+        .collect::<Vec<_>>();
+    // the rest of the removed ones were reaped by someone else (`ECHILD`)
+    registry.pids.retain(|&pid, _| {
+        children.is_watched(pid) || events.iter().any(|e| e.pid == pid)
+    });
+    Ok(events)
+}
+
+impl fmt::Debug for Reaper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registry = self.lock();
+        f.debug_struct("Reaper")
+        .field("pids", &registry.pids.keys().collect::<Vec<_>>())
+        .field("any", &registry.any.len())
+        .field("orphans", &registry.orphans)
+        .finish()
+    }
+}
@@ -0,0 +1,123 @@
+//! Waiting for several signal sources at once
+//!
+//! A program may have several logical sources of signals, e.g. a general
+//! `Trap` for termination signals and a dedicated one for `SIGCHLD` owned by
+//! a child-reaping component. `select_signals` waits for any of them and
+//! tells which one fired.
+//!
+//! This is implemented by polling a `signalfd` per source. Unless a `Trap`
+//! was created by `trap_via_signalfd()`, a temporary `signalfd` for its
+//! signal set is created for every call. Signals of all the sources must be
+//! blocked (which `Trap` does).
+//!
+//! Only available on linux.
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Instant;
+
+use libc;
+use nix;
+use nix::errno::Errno;
+use nix::poll::{ppoll, PollFd, PollFlags};
+use nix::sys::signal::SigSet;
+use nix::sys::signalfd::{SignalFd, SfdFlags};
+use nix::sys::time::{TimeSpec, TimeValLike};
+
+use ffi::read_signalfd;
+use trap::{Trap, Deadline};
+use Signal;
+
+
+/// Something that can be waited for signals by `select_signals`
+pub trait SignalSource {
+    /// The set of signals this source receives
+    fn signal_set(&self) -> SigSet;
+
+    /// File descriptor that is readable when any of the signals is pending
+    ///
+    /// If `None` is returned a temporary `signalfd` is created for the
+    /// `signal_set()`. If a descriptor is returned it must be a non-blocking
+    /// `signalfd` as signals are read from it directly.
+    fn signal_fd(&self) -> Option<RawFd> {
+        None
+    }
+}
+
+impl SignalSource for Trap {
+    fn signal_set(&self) -> SigSet {
+        self.sigset()
+    }
+    fn signal_fd(&self) -> Option<RawFd> {
+        Trap::signal_fd(self)
+    }
+}
+
+enum Fd {
+    Temporary(SignalFd),
+    Borrowed(RawFd),
+}
+
+impl Fd {
+    fn raw(&self) -> RawFd {
+        match *self {
+            Fd::Temporary(ref fd) => fd.as_raw_fd(),
+            Fd::Borrowed(fd) => fd,
+        }
+    }
+    fn read_signal(&mut self) -> nix::Result<Option<Signal>> {
+        let info = match *self {
+            Fd::Temporary(ref mut fd) => fd.read_signal()?,
+            Fd::Borrowed(fd) => read_signalfd(fd)?,
+        };
+        match info {
+            Some(info) => {
+                Ok(Some(Signal::from_c_int(info.ssi_signo as libc::c_int)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Wait until any of the sources receives a signal or deadline is reached
+///
+/// Returns the index of the source in `sources` and the signal, or `None`
+/// on timeout. If the signal sets overlap, the signal is attributed to any
+/// of the sources having it.
+pub fn select_signals(sources: &[&dyn SignalSource], deadline: Deadline)
+    -> nix::Result<Option<(usize, Signal)>>
+{
+    let mut fds = Vec::with_capacity(sources.len());
+    for source in sources {
+        fds.push(match source.signal_fd() {
+            Some(fd) => Fd::Borrowed(fd),
+            None => Fd::Temporary(SignalFd::with_flags(&source.signal_set(),
+                SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?),
+        });
+    }
+    let mask = SigSet::thread_get_mask()?;
+    loop {
+        let mut pollfds = fds.iter()
+            .map(|fd| PollFd::new(fd.raw(), PollFlags::POLLIN))
+            .collect::<Vec<_>>();
+        let timeout = deadline.instant()
+            .saturating_duration_since(Instant::now());
+        let timeout = TimeSpec::nanoseconds(timeout.as_nanos() as i64);
+        match ppoll(&mut pollfds, timeout, mask) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => return Err(e),
+        }
+        for (idx, pollfd) in pollfds.iter().enumerate() {
+            let ready = pollfd.revents()
+                .map(|r| r.contains(PollFlags::POLLIN))
+                .unwrap_or(false);
+            if ready {
+                if let Some(sig) = fds[idx].read_signal()? {
+                    return Ok(Some((idx, sig)));
+                }
+            }
+        }
+        // spurious wakeup: somebody else consumed the signal
+    }
+}
@@ -0,0 +1,93 @@
+//! Serde support for `Signal` and `SignalSet`
+//!
+//! A signal is serialized as its name (`"SIGTERM"`) and deserialized from
+//! anything its `FromStr` implementation accepts (`"SIGTERM"`, `"TERM"`,
+//! `"term"`) or from a number. A set is serialized as a sequence of
+//! signals, ordered by number.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use {Signal, SignalSet};
+
+
+impl Serialize for Signal {
+    fn serialize<S: Serializer>(&self, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+struct SignalVisitor;
+
+impl<'de> Visitor<'de> for SignalVisitor {
+    type Value = Signal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a signal name or number")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Signal, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Signal, E> {
+        i32::try_from(value).ok()
+            .and_then(|raw| Signal::from_c_int(raw).ok())
+            .ok_or_else(|| E::custom(format!("unknown signal {}", value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Signal, E> {
+        i32::try_from(value).ok()
+            .and_then(|raw| Signal::from_c_int(raw).ok())
+            .ok_or_else(|| E::custom(format!("unknown signal {}", value)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Signal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+        -> Result<Signal, D::Error>
+    {
+        deserializer.deserialize_any(SignalVisitor)
+    }
+}
+
+impl Serialize for SignalSet {
+    fn serialize<S: Serializer>(&self, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+struct SignalSetVisitor;
+
+impl<'de> Visitor<'de> for SignalSetVisitor {
+    type Value = SignalSet;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of signals")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A)
+        -> Result<SignalSet, A::Error>
+    {
+        let mut set = SignalSet::empty();
+        while let Some(sig) = seq.next_element()? {
+            set.insert(sig);
+        }
+        Ok(set)
+    }
+}
+
+impl<'de> Deserialize<'de> for SignalSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+        -> Result<SignalSet, D::Error>
+    {
+        deserializer.deserialize_seq(SignalSetVisitor)
+    }
+}
@@ -0,0 +1,393 @@
+//! Information about a delivered signal
+//!
+//! When a signal is received with `SA_SIGINFO` handler or by
+//! `sigwaitinfo`-style functions the kernel fills in `siginfo_t`, which tells
+//! a lot more than just a signal number. Most important is `si_code` which
+//! tells *why* the signal was sent (e.g. whether it was sent by `kill` or
+//! generated by the kernel on memory access violation).
+//!
+//! Decoding signal-specific codes is only implemented for linux, on other
+//! systems they are reported as `SigCode::Unknown`.
+
+use std::fmt;
+
+use libc::{self, c_int, pid_t, uid_t};
+use Signal;
+use from_raw;
+
+
+/// Reason of the signal, decoded from `si_code`
+///
+/// Signal-specific codes (`Segv*`, `Bus*`, `Child*`, ...) are only returned
+/// for the respective signals, because different signals reuse the same
+/// numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigCode {
+    /// Sent by `kill()`
+    User,
+    /// Sent by the kernel
+    Kernel,
+    /// Sent by `sigqueue()`
+    Queue,
+    /// POSIX timer expired
+    Timer,
+    /// POSIX message queue state changed
+    MessageQueue,
+    /// Asynchronous I/O completed
+    AsyncIo,
+    /// Queued `SIGIO`
+    SigIo,
+    /// Sent by `tkill()` or `tgkill()`
+    Tkill,
+
+    /// `SIGSEGV`: address not mapped to object
+    SegvMapErr,
+    /// `SIGSEGV`: invalid permissions for mapped object
+    SegvAccErr,
+
+    /// `SIGBUS`: invalid address alignment
+    BusAdrAln,
+    /// `SIGBUS`: nonexistent physical address
+    BusAdrErr,
+    /// `SIGBUS`: object-specific hardware error
+    BusObjErr,
+
+    /// `SIGILL`: illegal opcode
+    IllOpc,
+    /// `SIGILL`: illegal operand
+    IllOpn,
+    /// `SIGILL`: illegal addressing mode
+    IllAdr,
+    /// `SIGILL`: illegal trap
+    IllTrp,
+    /// `SIGILL`: privileged opcode
+    IllPrvOpc,
+    /// `SIGILL`: privileged register
+    IllPrvReg,
+    /// `SIGILL`: coprocessor error
+    IllCoproc,
+    /// `SIGILL`: internal stack error
+    IllBadStk,
+
+    /// `SIGFPE`: integer divide by zero
+    FpeIntDiv,
+    /// `SIGFPE`: integer overflow
+    FpeIntOvf,
+    /// `SIGFPE`: floating-point divide by zero
+    FpeFltDiv,
+    /// `SIGFPE`: floating-point overflow
+    FpeFltOvf,
+    /// `SIGFPE`: floating-point underflow
+    FpeFltUnd,
+    /// `SIGFPE`: floating-point inexact result
+    FpeFltRes,
+    /// `SIGFPE`: floating-point invalid operation
+    FpeFltInv,
+    /// `SIGFPE`: subscript out of range
+    FpeFltSub,
+
+    /// `SIGCHLD`: child has exited
+    ChildExited,
+    /// `SIGCHLD`: child was killed
+    ChildKilled,
+    /// `SIGCHLD`: child terminated abnormally (and dumped core)
+    ChildDumped,
+    /// `SIGCHLD`: traced child has trapped
+    ChildTrapped,
+    /// `SIGCHLD`: child has stopped
+    ChildStopped,
+    /// `SIGCHLD`: stopped child has continued
+    ChildContinued,
+
+    /// `SIGTRAP`: process breakpoint
+    TrapBreakpoint,
+    /// `SIGTRAP`: process trace trap
+    TrapTrace,
+
+    /// `SIGIO`: data input available
+    PollIn,
+    /// `SIGIO`: output buffers available
+    PollOut,
+    /// `SIGIO`: input message available
+    PollMsg,
+    /// `SIGIO`: I/O error
+    PollErr,
+    /// `SIGIO`: high priority input available
+    PollPri,
+    /// `SIGIO`: device disconnected
+    PollHup,
+
+    /// The code is not known to this library
+    Unknown(c_int),
+}
+
+/// Information about a received signal
+#[derive(Debug, Clone, Copy)]
+pub struct SigInfo {
+    signo: c_int,
+    code: c_int,
+    pid: pid_t,
+    uid: uid_t,
+    status: c_int,
+    value: usize,
+}
+
+impl SigInfo {
+    /// Returns the signal
+    ///
+    /// Returns `None` if signal number is not representable by `Signal`
+    /// (e.g. for realtime signals)
+    pub fn signal(&self) -> Option<Signal> {
+        from_raw(self.signo)
+    }
+
+    /// Returns raw signal number
+    pub fn raw_signal(&self) -> c_int {
+        self.signo
+    }
+
+    /// Returns raw `si_code` value
+    pub fn raw_code(&self) -> c_int {
+        self.code
+    }
+
+    /// Returns the reason of the signal decoded from `si_code`
+    pub fn code(&self) -> SigCode {
+        SigCode::decode(self.signo, self.code)
+    }
+
+    /// Returns `true` if the signal was sent by a process rather than
+    /// generated by the kernel
+    ///
+    /// This is the case for signals sent by `kill()`, `sigqueue()` and
+    /// `tkill()`/`tgkill()`, the latter is also what `raise()` uses on linux.
+    /// Note that it doesn't tell whether the sender is privileged, check the
+    /// sender uid for that.
+    pub fn is_from_user(&self) -> bool {
+        matches!(self.code(),
+                 SigCode::User | SigCode::Queue | SigCode::Tkill)
+    }
+
+    /// Returns `true` if the sender pid and uid fields are meaningful
+    fn has_sender(&self) -> bool {
+        self.is_from_user() || self.signo == libc::SIGCHLD
+    }
+
+    /// Returns pid of the process that sent the signal
+    ///
+    /// For `SIGCHLD` this is the pid of the child that changed state. For
+    /// signals generated by the kernel `None` is returned.
+    pub fn sender_pid(&self) -> Option<pid_t> {
+        if self.has_sender() { Some(self.pid) } else { None }
+    }
+
+    /// Returns real uid of the process that sent the signal
+    ///
+    /// Returns `None` in the same cases as `sender_pid()`.
+    pub fn sender_uid(&self) -> Option<uid_t> {
+        if self.has_sender() { Some(self.uid) } else { None }
+    }
+
+    /// Returns the value sent along with the signal by `sigqueue()`
+    ///
+    /// This is `si_value` (as a pointer-sized integer, e.g. the value passed
+    /// to `realtime::send_queued()`). It's also filled in for POSIX timers
+    /// and message queue notifications. Returns `None` for other signals.
+    /// If the sender used the `sival_int` member, only the lower 32 bits are
+    /// meaningful.
+    pub fn value(&self) -> Option<usize> {
+        match self.code() {
+            SigCode::Queue | SigCode::Timer | SigCode::MessageQueue => {
+                Some(self.value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `si_status` of `SIGCHLD` sent by the kernel
+    ///
+    /// This is the exit code if the child has exited (`code()` is
+    /// `SigCode::ChildExited`), otherwise the signal that killed, stopped or
+    /// continued the child. The child is identified by `sender_pid()`.
+    /// Returns `None` for other signals and for `SIGCHLD` sent by a process.
+    ///
+    /// Note that `SIGCHLD` of several children changing state at once are
+    /// coalesced into one, so this describes only one of them. And the
+    /// child is not reaped by receiving the signal. So the `waitpid` loop
+    /// can only be skipped if there is a single child, and a zombie is left
+    /// until it's reaped.
+    pub fn child_status(&self) -> Option<c_int> {
+        use self::SigCode::*;
+
+        match self.code() {
+            ChildExited | ChildKilled | ChildDumped | ChildTrapped |
+            ChildStopped | ChildContinued => Some(self.status),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<&'a libc::siginfo_t> for SigInfo {
+    fn from(info: &'a libc::siginfo_t) -> SigInfo {
+        // the fields are only meaningful for some codes, see `has_sender`
+        // and `child_status`, `value`
+        let (pid, uid, status, value) = unsafe {
+            (info.si_pid(), info.si_uid(), info.si_status(),
+             info.si_value().sival_ptr as usize)
+        };
+        SigInfo {
+            signo: info.si_signo,
+            code: info.si_code,
+            pid,
+            uid,
+            status,
+            value,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> From<&'a libc::signalfd_siginfo> for SigInfo {
+    fn from(info: &'a libc::signalfd_siginfo) -> SigInfo {
+        SigInfo {
+            signo: info.ssi_signo as c_int,
+            code: info.ssi_code,
+            pid: info.ssi_pid as pid_t,
+            uid: info.ssi_uid as uid_t,
+            status: info.ssi_status,
+            value: info.ssi_ptr as usize,
+        }
+    }
+}
+
+impl SigCode {
+    /// Decode the `si_code` of specified signal
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn decode(signo: c_int, code: c_int) -> SigCode {
+        use self::SigCode::*;
+
+        match code {
+            libc::SI_USER => return User,
+            libc::SI_KERNEL => return Kernel,
+            libc::SI_QUEUE => return Queue,
+            libc::SI_TIMER => return Timer,
+            libc::SI_MESGQ => return MessageQueue,
+            libc::SI_ASYNCIO => return AsyncIo,
+            libc::SI_SIGIO => return SigIo,
+            libc::SI_TKILL => return Tkill,
+            _ => {}
+        }
+        match (signo, code) {
+            (libc::SIGSEGV, 1) => SegvMapErr,
+            (libc::SIGSEGV, 2) => SegvAccErr,
+
+            (libc::SIGBUS, 1) => BusAdrAln,
+            (libc::SIGBUS, 2) => BusAdrErr,
+            (libc::SIGBUS, 3) => BusObjErr,
+
+            (libc::SIGILL, 1) => IllOpc,
+            (libc::SIGILL, 2) => IllOpn,
+            (libc::SIGILL, 3) => IllAdr,
+            (libc::SIGILL, 4) => IllTrp,
+            (libc::SIGILL, 5) => IllPrvOpc,
+            (libc::SIGILL, 6) => IllPrvReg,
+            (libc::SIGILL, 7) => IllCoproc,
+            (libc::SIGILL, 8) => IllBadStk,
+
+            (libc::SIGFPE, 1) => FpeIntDiv,
+            (libc::SIGFPE, 2) => FpeIntOvf,
+            (libc::SIGFPE, 3) => FpeFltDiv,
+            (libc::SIGFPE, 4) => FpeFltOvf,
+            (libc::SIGFPE, 5) => FpeFltUnd,
+            (libc::SIGFPE, 6) => FpeFltRes,
+            (libc::SIGFPE, 7) => FpeFltInv,
+            (libc::SIGFPE, 8) => FpeFltSub,
+
+            (libc::SIGCHLD, libc::CLD_EXITED) => ChildExited,
+            (libc::SIGCHLD, libc::CLD_KILLED) => ChildKilled,
+            (libc::SIGCHLD, libc::CLD_DUMPED) => ChildDumped,
+            (libc::SIGCHLD, libc::CLD_TRAPPED) => ChildTrapped,
+            (libc::SIGCHLD, libc::CLD_STOPPED) => ChildStopped,
+            (libc::SIGCHLD, libc::CLD_CONTINUED) => ChildContinued,
+
+            (libc::SIGTRAP, libc::TRAP_BRKPT) => TrapBreakpoint,
+            (libc::SIGTRAP, libc::TRAP_TRACE) => TrapTrace,
+
+            (libc::SIGIO, 1) => PollIn,
+            (libc::SIGIO, 2) => PollOut,
+            (libc::SIGIO, 3) => PollMsg,
+            (libc::SIGIO, 4) => PollErr,
+            (libc::SIGIO, 5) => PollPri,
+            (libc::SIGIO, 6) => PollHup,
+
+            (_, code) => Unknown(code),
+        }
+    }
+
+    /// Decode the `si_code` of specified signal
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn decode(_signo: c_int, code: c_int) -> SigCode {
+        SigCode::Unknown(code)
+    }
+
+    /// Returns short human-readable description of the code
+    pub fn description(&self) -> &'static str {
+        use self::SigCode::*;
+
+        match *self {
+            User => "sent by kill",
+            Kernel => "sent by kernel",
+            Queue => "sent by sigqueue",
+            Timer => "timer expired",
+            MessageQueue => "message queue state changed",
+            AsyncIo => "asynchronous I/O completed",
+            SigIo => "queued SIGIO",
+            Tkill => "sent by tkill",
+            SegvMapErr => "address not mapped to object",
+            SegvAccErr => "invalid permissions for mapped object",
+            BusAdrAln => "invalid address alignment",
+            BusAdrErr => "nonexistent physical address",
+            BusObjErr => "object-specific hardware error",
+            IllOpc => "illegal opcode",
+            IllOpn => "illegal operand",
+            IllAdr => "illegal addressing mode",
+            IllTrp => "illegal trap",
+            IllPrvOpc => "privileged opcode",
+            IllPrvReg => "privileged register",
+            IllCoproc => "coprocessor error",
+            IllBadStk => "internal stack error",
+            FpeIntDiv => "integer divide by zero",
+            FpeIntOvf => "integer overflow",
+            FpeFltDiv => "floating-point divide by zero",
+            FpeFltOvf => "floating-point overflow",
+            FpeFltUnd => "floating-point underflow",
+            FpeFltRes => "floating-point inexact result",
+            FpeFltInv => "floating-point invalid operation",
+            FpeFltSub => "subscript out of range",
+            ChildExited => "child has exited",
+            ChildKilled => "child was killed",
+            ChildDumped => "child terminated abnormally",
+            ChildTrapped => "traced child has trapped",
+            ChildStopped => "child has stopped",
+            ChildContinued => "stopped child has continued",
+            TrapBreakpoint => "process breakpoint",
+            TrapTrace => "process trace trap",
+            PollIn => "data input available",
+            PollOut => "output buffers available",
+            PollMsg => "input message available",
+            PollErr => "I/O error",
+            PollPri => "high priority input available",
+            PollHup => "device disconnected",
+            Unknown(_) => "unknown reason",
+        }
+    }
+}
+
+impl fmt::Display for SigCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SigCode::Unknown(code) => write!(f, "unknown reason ({})", code),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Crate-owned signal type
+//!
+//! `Signal` mirrors the standard signals of the platform. It's used in the
+//! public API instead of `nix::sys::signal::Signal`, so that a new version of
+//! `nix` doesn't change the API of this crate. Use `From` and `Into` to
+//! convert it to and from the `nix` type and raw signal numbers.
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use libc::{self, c_int};
+use nix::sys::signal::Signal as NixSignal;
+
+use Error;
+
+
+/// Signals are numbered from one, and standard ones fit in the first 64
+pub(crate) const MAX_SIGNAL: c_int = 64;
+
+macro_rules! signals {
+    ($( $(#[$attr:meta])* $name:ident = $descr:expr, )*) => {
+        /// Signal number
+        ///
+        /// Only standard signals are represented, realtime signals are
+        /// handled as raw numbers (see the `realtime` module). Variants have
+        /// the values of the respective `libc` constants.
+        #[repr(i32)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub enum Signal {
+            $(
+                $(#[$attr])*
+                #[doc = concat!("`", stringify!($name), "`")]
+                $name = libc::$name,
+            )*
+        }
+
+        impl Signal {
+            /// Returns the name of the signal, e.g. `"SIGTERM"`
+            pub fn as_str(&self) -> &'static str {
+                match *self {
+                    $( $(#[$attr])* Signal::$name => stringify!($name), )*
+                }
+            }
+
+            /// Returns a short description of the signal, e.g. `"terminated"`
+            ///
+            /// The descriptions are similar to the ones of `strsignal`, but
+            /// don't depend on the C library or the locale.
+            pub fn description(&self) -> &'static str {
+                match *self {
+                    $( $(#[$attr])* Signal::$name => $descr, )*
+                }
+            }
+
+            /// Convert the raw signal number into the signal
+            ///
+            /// Returns `Error::InvalidSignal` if the number isn't a standard
+            /// signal of the platform.
+            pub fn from_c_int(raw: c_int) -> Result<Signal, Error> {
+                match raw {
+                    $( $(#[$attr])* libc::$name => Ok(Signal::$name), )*
+                    _ => Err(Error::InvalidSignal(raw)),
+                }
+            }
+        }
+    }
+}
+
+signals! {
+    SIGHUP = "hangup",
+    SIGINT = "interrupt",
+    SIGQUIT = "quit",
+    SIGILL = "illegal instruction",
+    SIGTRAP = "trace/breakpoint trap",
+    SIGABRT = "aborted",
+    SIGBUS = "bus error",
+    SIGFPE = "floating point exception",
+    SIGKILL = "killed",
+    SIGUSR1 = "user defined signal 1",
+    SIGSEGV = "segmentation fault",
+    SIGUSR2 = "user defined signal 2",
+    SIGPIPE = "broken pipe",
+    SIGALRM = "alarm clock",
+    SIGTERM = "terminated",
+    #[cfg(all(any(target_os = "android", target_os = "emscripten",
+                  target_os = "linux"),
+              not(any(target_arch = "mips", target_arch = "mips64",
+                      target_arch = "sparc64"))))]
+    SIGSTKFLT = "stack fault",
+    SIGCHLD = "child exited",
+    SIGCONT = "continued",
+    SIGSTOP = "stopped (signal)",
+    SIGTSTP = "stopped",
+    SIGTTIN = "stopped (tty input)",
+    SIGTTOU = "stopped (tty output)",
+    SIGURG = "urgent I/O condition",
+    SIGXCPU = "CPU time limit exceeded",
+    SIGXFSZ = "file size limit exceeded",
+    SIGVTALRM = "virtual timer expired",
+    SIGPROF = "profiling timer expired",
+    SIGWINCH = "window changed",
+    SIGIO = "I/O possible",
+    #[cfg(any(target_os = "android", target_os = "emscripten",
+              target_os = "linux"))]
+    SIGPWR = "power failure",
+    SIGSYS = "bad system call",
+    #[cfg(not(any(target_os = "android", target_os = "emscripten",
+                  target_os = "linux")))]
+    SIGEMT = "EMT trap",
+    #[cfg(not(any(target_os = "android", target_os = "emscripten",
+                  target_os = "linux")))]
+    SIGINFO = "information request",
+}
+
+impl Signal {
+    /// Iterate over all the signals of the platform, ordered by number
+    pub fn iterator() -> impl Iterator<Item=Signal> + Clone {
+        (1..MAX_SIGNAL).filter_map(|raw| Signal::from_c_int(raw).ok())
+    }
+}
+
+/// Formats the name of the signal, e.g. `SIGTERM`
+///
+/// The alternate form (`{:#}`) adds the description: `SIGTERM (terminated)`.
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{} ({})", self.as_str(), self.description())
+        } else {
+            f.write_str(self.as_str())
+        }
+    }
+}
+
+/// Parses the name or the number of the signal
+///
+/// Accepts the full name (`SIGTERM`), the name without the `SIG` prefix
+/// (`TERM`) and the number (`15`). Names are case-insensitive, so the usual
+/// spellings of `kill -s` and of config files all work.
+impl FromStr for Signal {
+    type Err = ParseSignalError;
+    fn from_str(s: &str) -> Result<Signal, ParseSignalError> {
+        let err = || ParseSignalError { input: s.to_string() };
+        if let Ok(raw) = s.parse::<c_int>() {
+            return Signal::from_c_int(raw).map_err(|_| err());
+        }
+        let name = s.get(..3)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("SIG"))
+            .map_or(s, |_| &s[3..]);
+        Signal::iterator()
+            .find(|sig| sig.as_str()[3..].eq_ignore_ascii_case(name))
+            .ok_or_else(err)
+    }
+}
+
+/// Error returned when parsing a signal fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSignalError {
+    input: String,
+}
+
+impl ParseSignalError {
+    /// Returns the string that failed to parse
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+}
+
+impl fmt::Display for ParseSignalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown signal {:?}", self.input)
+    }
+}
+
+impl error::Error for ParseSignalError {}
+
+impl AsRef<str> for Signal {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<Signal> for NixSignal {
+    fn from(sig: Signal) -> NixSignal {
+        // both mirror the same `libc` constants
+        NixSignal::from_c_int(sig as c_int)
+            .expect("nix knows all the signals")
+    }
+}
+
+impl From<NixSignal> for Signal {
+    fn from(sig: NixSignal) -> Signal {
+        Signal::from_c_int(sig as c_int)
+            .expect("all the nix signals are known")
+    }
+}
+
+impl From<Signal> for c_int {
+    fn from(sig: Signal) -> c_int {
+        sig as c_int
+    }
+}
+
+impl TryFrom<c_int> for Signal {
+    type Error = Error;
+    fn try_from(raw: c_int) -> Result<Signal, Error> {
+        Signal::from_c_int(raw)
+    }
+}
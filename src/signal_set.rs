@@ -0,0 +1,243 @@
+//! A set of signals
+//!
+//! `SignalSet` is a set of `Signal` values backed by the system `sigset_t`,
+//! so it converts to and from the `SigSet` that `nix` and the library use
+//! for signal masks without copying signals one by one. Sets can be
+//! combined with the usual set operations, also available as the `|`, `&`
+//! and `-` operators.
+//!
+//! Only standard signals are represented. A set converted from a `SigSet`
+//! keeps any realtime signals of the original, so converting it back
+//! doesn't lose them, but they aren't visible to the methods of the set.
+
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{BitAnd, BitOr, Sub};
+
+use libc::c_int;
+use nix::sys::signal::SigSet;
+
+use signal::MAX_SIGNAL;
+use Signal;
+
+
+/// A set of signals
+#[derive(Clone, Copy)]
+pub struct SignalSet {
+    sigset: SigSet,
+}
+
+/// Iterator over the signals of a `SignalSet`, ordered by number
+#[derive(Debug, Clone)]
+pub struct Iter {
+    set: SignalSet,
+    next: c_int,
+}
+
+impl SignalSet {
+    /// Create an empty set
+    pub fn empty() -> SignalSet {
+        SignalSet { sigset: SigSet::empty() }
+    }
+
+    /// Create a set of all the signals of the platform
+    ///
+    /// Note: this includes `SIGKILL` and `SIGSTOP`, which can't be trapped.
+    pub fn all() -> SignalSet {
+        Signal::iterator().collect()
+    }
+
+    /// Add the signal to the set
+    pub fn insert(&mut self, sig: Signal) {
+        self.sigset.add(sig.into());
+    }
+
+    /// Remove the signal from the set
+    pub fn remove(&mut self, sig: Signal) {
+        self.sigset.remove(sig.into());
+    }
+
+    /// Returns `true` if the set contains the signal
+    pub fn contains(&self, sig: Signal) -> bool {
+        self.sigset.contains(sig.into())
+    }
+
+    /// Returns `true` if the set contains no signals
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Returns the number of signals in the set
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Iterate over the signals of the set, ordered by number
+    pub fn iter(&self) -> Iter {
+        Iter { set: *self, next: 1 }
+    }
+
+    /// Returns the signals that are in either of the sets
+    pub fn union(&self, other: &SignalSet) -> SignalSet {
+        let mut set = *self;
+        set.extend(other);
+        set
+    }
+
+    /// Returns the signals that are in both sets
+    pub fn intersection(&self, other: &SignalSet) -> SignalSet {
+        self.iter().filter(|&sig| other.contains(sig)).collect()
+    }
+
+    /// Returns the signals of this set that aren't in the other one
+    pub fn difference(&self, other: &SignalSet) -> SignalSet {
+        self.iter().filter(|&sig| !other.contains(sig)).collect()
+    }
+
+    /// Returns `true` if all the signals of this set are in the other one
+    pub fn is_subset(&self, other: &SignalSet) -> bool {
+        self.iter().all(|sig| other.contains(sig))
+    }
+
+    /// Returns the underlying `SigSet`
+    pub fn as_sigset(&self) -> &SigSet {
+        &self.sigset
+    }
+}
+
+impl Iterator for Iter {
+    type Item = Signal;
+    fn next(&mut self) -> Option<Signal> {
+        while self.next < MAX_SIGNAL {
+            let raw = self.next;
+            self.next += 1;
+            if let Ok(sig) = Signal::from_c_int(raw) {
+                if self.set.contains(sig) {
+                    return Some(sig);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Default for SignalSet {
+    fn default() -> SignalSet {
+        SignalSet::empty()
+    }
+}
+
+impl PartialEq for SignalSet {
+    fn eq(&self, other: &SignalSet) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for SignalSet {}
+
+impl fmt::Debug for SignalSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Formats the names of the signals separated by commas
+///
+/// E.g. `SIGHUP, SIGINT, SIGTERM`. An empty set is formatted as `none`.
+impl fmt::Display for SignalSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+        for (idx, sig) in self.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(sig.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<Signal> for SignalSet {
+    fn from_iter<I: IntoIterator<Item=Signal>>(iter: I) -> SignalSet {
+        let mut set = SignalSet::empty();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<'a> FromIterator<&'a Signal> for SignalSet {
+    fn from_iter<I: IntoIterator<Item=&'a Signal>>(iter: I) -> SignalSet {
+        iter.into_iter().cloned().collect()
+    }
+}
+
+impl Extend<Signal> for SignalSet {
+    fn extend<I: IntoIterator<Item=Signal>>(&mut self, iter: I) {
+        for sig in iter {
+            self.insert(sig);
+        }
+    }
+}
+
+impl IntoIterator for SignalSet {
+    type Item = Signal;
+    type IntoIter = Iter;
+    fn into_iter(self) -> Iter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for &SignalSet {
+    type Item = Signal;
+    type IntoIter = Iter;
+    fn into_iter(self) -> Iter {
+        self.iter()
+    }
+}
+
+impl<'a> From<&'a [Signal]> for SignalSet {
+    fn from(signals: &'a [Signal]) -> SignalSet {
+        signals.iter().collect()
+    }
+}
+
+impl From<SigSet> for SignalSet {
+    fn from(sigset: SigSet) -> SignalSet {
+        SignalSet { sigset }
+    }
+}
+
+impl From<SignalSet> for SigSet {
+    fn from(set: SignalSet) -> SigSet {
+        set.sigset
+    }
+}
+
+impl AsRef<SigSet> for SignalSet {
+    fn as_ref(&self) -> &SigSet {
+        &self.sigset
+    }
+}
+
+impl BitOr for SignalSet {
+    type Output = SignalSet;
+    fn bitor(self, other: SignalSet) -> SignalSet {
+        self.union(&other)
+    }
+}
+
+impl BitAnd for SignalSet {
+    type Output = SignalSet;
+    fn bitand(self, other: SignalSet) -> SignalSet {
+        self.intersection(&other)
+    }
+}
+
+impl Sub for SignalSet {
+    type Output = SignalSet;
+    fn sub(self, other: SignalSet) -> SignalSet {
+        self.difference(&other)
+    }
+}
@@ -0,0 +1,227 @@
+//! Receiving signals via a file descriptor
+//!
+//! `SignalFd` masks out the signals the same way `Trap` does and creates a
+//! `signalfd` for them. The descriptor is readable whenever one of the
+//! signals is pending, so it can be registered in an existing `epoll` (or
+//! `poll`, `mio`...) loop. The descriptor is non-blocking: read signals
+//! with `read()` when it's readable.
+//!
+//! With the `tokio` feature, `tokio::TokioSignalStream` registers the
+//! descriptor in the tokio reactor.
+//!
+//! With the `mio` feature, `SignalFd` implements `mio::event::Source`.
+//! Since mio readiness is edge-triggered, call `try_recv()` on every event
+//! until it returns a `WouldBlock` error, otherwise the remaining signals
+//! aren't reported again.
+//!
+//! With the `calloop` feature, `SignalFd` implements `calloop::EventSource`:
+//! insert it into the event loop, and the callback is called with the
+//! `SigInfo` of every received signal.
+//!
+//! Only available on linux.
+
+use std::fmt;
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::time::Instant;
+
+use nix;
+use Signal;
+use nix::sys::signalfd::{signalfd, SfdFlags, SIGNALFD_NEW};
+use nix::unistd;
+
+#[cfg(feature = "mio")]
+use mio::{event, unix::SourceFd, Interest, Registry, Token};
+#[cfg(feature = "calloop")]
+use calloop::{EventSource, Poll, PostAction, Readiness, TokenFactory};
+
+use ffi::{read_signalfd, wait_readable};
+use select::SignalSource;
+use siginfo::SigInfo;
+use trap::Trap;
+
+
+/// A file descriptor receiving the specified signals
+///
+/// Like with `Trap`, signal mask and handlers are restored when this
+/// object is dropped, and it should be dropped in the thread where it was
+/// created. The signals must be blocked in all other threads too (e.g. by
+/// creating it before spawning any threads).
+pub struct SignalFd {
+    fd: RawFd,
+    trap: Trap,
+    #[cfg(feature = "calloop")]
+    token: Option<calloop::Token>,
+}
+
+impl SignalFd {
+    /// Mask out the signals and create the descriptor receiving them
+    pub fn new(signals: &[Signal]) -> nix::Result<SignalFd> {
+        let trap = Trap::trap_checked(signals)?;
+        let fd = signalfd(SIGNALFD_NEW, &trap.sigset(),
+                          SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?;
+        Ok(SignalFd {
+            fd,
+            trap,
+            #[cfg(feature = "calloop")]
+            token: None,
+        })
+    }
+
+    /// Read a pending signal
+    ///
+    /// Returns `None` if no signal is pending, never blocks.
+    pub fn read(&self) -> nix::Result<Option<SigInfo>> {
+        Ok(read_signalfd(self.fd)?
+            .map(|info| self.trap.received_info(SigInfo::from(&info))))
+    }
+
+    /// Read a pending signal, for use with readiness-based event loops
+    ///
+    /// Same as `read()` but returns an `io::ErrorKind::WouldBlock` error if
+    /// no signal is pending, which makes it usable directly in
+    /// `AsyncFd::try_io()` and similar APIs.
+    pub fn try_recv(&self) -> io::Result<SigInfo> {
+        match self.read() {
+            Ok(Some(info)) => Ok(info),
+            Ok(None) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(nix::Error::Sys(errno)) => {
+                Err(io::Error::from_raw_os_error(errno as i32))
+            }
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    /// Wait until a signal arrives or deadline is reached
+    ///
+    /// Unlike `Trap::wait()`, `EINTR` is returned as an error.
+    pub fn wait(&self, deadline: Instant) -> nix::Result<Option<SigInfo>> {
+        loop {
+            if let Some(info) = self.read()? {
+                return Ok(Some(info));
+            }
+            if !wait_readable(self.fd, Some(deadline))? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns the trap that masks out the signals
+    pub fn trap(&self) -> &Trap {
+        &self.trap
+    }
+}
+
+impl AsRawFd for SignalFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl AsFd for SignalFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // the descriptor is open until the `SignalFd` is dropped
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
+#[cfg(feature = "mio")]
+impl event::Source for SignalFd {
+    fn register(&mut self, registry: &Registry, token: Token,
+        interests: Interest)
+        -> io::Result<()>
+    {
+        SourceFd(&self.fd).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token,
+        interests: Interest)
+        -> io::Result<()>
+    {
+        SourceFd(&self.fd).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.fd).deregister(registry)
+    }
+}
+
+/// Calls the callback for each signal read, until none is pending
+#[cfg(feature = "calloop")]
+impl EventSource for SignalFd {
+    type Event = SigInfo;
+    type Metadata = ();
+    type Ret = ();
+    type Error = io::Error;
+
+    fn process_events<F>(&mut self, _readiness: Readiness,
+        token: calloop::Token, mut callback: F)
+        -> io::Result<PostAction>
+        where F: FnMut(SigInfo, &mut ())
+    {
+        if self.token != Some(token) {
+            return Ok(PostAction::Continue);
+        }
+        loop {
+            match self.try_recv() {
+                Ok(info) => callback(info, &mut ()),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(PostAction::Continue);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn register(&mut self, poll: &mut Poll, factory: &mut TokenFactory)
+        -> calloop::Result<()>
+    {
+        let token = factory.token();
+        // the event loop unregisters the source before dropping it
+        unsafe {
+            poll.register(&*self, calloop::Interest::READ,
+                calloop::Mode::Level, token)?;
+        }
+        self.token = Some(token);
+        Ok(())
+    }
+
+    fn reregister(&mut self, poll: &mut Poll, factory: &mut TokenFactory)
+        -> calloop::Result<()>
+    {
+        let token = factory.token();
+        poll.reregister(&*self, calloop::Interest::READ,
+            calloop::Mode::Level, token)?;
+        self.token = Some(token);
+        Ok(())
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop::Result<()> {
+        self.token = None;
+        poll.unregister(&*self)
+    }
+}
+
+impl SignalSource for SignalFd {
+    fn signal_set(&self) -> nix::sys::signal::SigSet {
+        self.trap.sigset()
+    }
+    fn signal_fd(&self) -> Option<RawFd> {
+        Some(self.fd)
+    }
+}
+
+impl Drop for SignalFd {
+    fn drop(&mut self) {
+        // the trap is dropped afterwards, restoring the mask
+        let _ = unistd::close(self.fd);
+    }
+}
+
+impl fmt::Debug for SignalFd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignalFd")
+            .field("fd", &self.fd)
+            .finish()
+    }
+}
@@ -0,0 +1,184 @@
+//! A thread receiving signals with `sigwait`, used by `Backend::Thread`
+//!
+//! The thread takes the signals as soon as they arrive and writes their
+//! siginfo into a pipe, so that waiting with a deadline is polling the read
+//! end of the pipe. This works on any unix system, including the ones that
+//! have neither `sigtimedwait` nor `kqueue`.
+
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::os::unix::thread::JoinHandleExt;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+use libc;
+use nix;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::sys::signal::SigSet;
+use nix::unistd;
+
+use {to_raw, Signal};
+
+
+/// The helper thread and the read end of its pipe
+///
+/// The thread is stopped when this object is dropped.
+pub struct SigwaitThread {
+    fd: RawFd,
+    stop: Arc<AtomicBool>,
+    // a signal of the set, sent to the thread to interrupt `sigwait`
+    wakeup: Signal,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SigwaitThread {
+    /// Spawn the thread waiting for the signals of the set
+    ///
+    /// The signals must be blocked in the calling thread, since the new
+    /// thread inherits its mask. Returns `EINVAL` if the set is empty.
+    pub fn spawn(sigset: &SigSet) -> nix::Result<SigwaitThread> {
+        let wakeup = Signal::iterator()
+            .find(|&sig| sigset.contains(sig.into()))
+            .ok_or(nix::Error::Sys(Errno::EINVAL))?;
+        let (fd, write) = unistd::pipe()?;
+        let setup = [fd, write].iter().try_for_each(|&fd| {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+            fcntl(fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+            Ok(())
+        });
+        if let Err(e) = setup {
+            let _ = unistd::close(fd);
+            let _ = unistd::close(write);
+            return Err(e);
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let sigset = *sigset;
+        let thread = thread::Builder::new()
+            .name("signal-sigwait".into())
+            .spawn(move || run(&sigset, write, &thread_stop));
+        match thread {
+            Ok(thread) => {
+                Ok(SigwaitThread { fd, stop, wakeup, thread: Some(thread) })
+            }
+            Err(e) => {
+                let _ = unistd::close(fd);
+                let _ = unistd::close(write);
+                Err(nix::Error::Sys(errno_of(&e)))
+            }
+        }
+    }
+
+    /// Returns the read end of the pipe
+    ///
+    /// It's readable when a signal is received (or when the thread failed,
+    /// in which case `read()` returns an error).
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Read a signal taken by the thread
+    ///
+    /// Returns `None` if there is none, never blocks.
+    pub fn read(&self) -> nix::Result<Option<libc::siginfo_t>> {
+        unsafe {
+            let mut info: libc::siginfo_t = mem::zeroed();
+            let size = mem::size_of::<libc::siginfo_t>();
+            let res = libc::read(self.fd,
+                &mut info as *mut _ as *mut libc::c_void, size);
+            if res < 0 {
+                match Errno::last() {
+                    Errno::EAGAIN => Ok(None),
+                    errno => Err(nix::Error::Sys(errno)),
+                }
+            } else if res as usize == size {
+                Ok(Some(info))
+            } else if res == 0 {
+                // the thread has exited because `sigwait` failed
+                Err(nix::Error::Sys(Errno::EPIPE))
+            } else {
+                // records are written atomically, so this isn't expected
+                Err(nix::Error::Sys(Errno::EIO))
+            }
+        }
+    }
+
+    /// Stop the thread
+    ///
+    /// Signals it has already taken can still be read afterwards.
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            unsafe {
+                libc::pthread_kill(thread.as_pthread_t(), to_raw(self.wakeup));
+            }
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SigwaitThread {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = unistd::close(self.fd);
+    }
+}
+
+fn run(sigset: &SigSet, fd: RawFd, stop: &AtomicBool) {
+    while let Ok(info) = wait(sigset) {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        // writes of less than `PIPE_BUF` bytes are atomic
+        let bytes = unsafe {
+            slice::from_raw_parts(&info as *const _ as *const u8,
+                                  mem::size_of::<libc::siginfo_t>())
+        };
+        match unistd::write(fd, bytes) {
+            Ok(_) => {}
+            // nobody reads the pipe, drop the signal like the kernel
+            // coalesces pending ones
+            Err(nix::Error::Sys(Errno::EAGAIN)) => {}
+            Err(_) => break,
+        }
+    }
+    let _ = unistd::close(fd);
+}
+
+#[cfg(target_os = "linux")]
+fn wait(sigset: &SigSet) -> nix::Result<libc::siginfo_t> {
+    loop {
+        let mut info = unsafe { mem::zeroed() };
+        if unsafe { libc::sigwaitinfo(sigset.as_ref(), &mut info) } > 0 {
+            return Ok(info);
+        }
+        if Errno::last() != Errno::EINTR {
+            return Err(nix::Error::last());
+        }
+    }
+}
+
+/// Only the signal number is filled in, as `sigwait` doesn't tell more
+#[cfg(not(target_os = "linux"))]
+fn wait(sigset: &SigSet) -> nix::Result<libc::siginfo_t> {
+    let mut sig = 0;
+    loop {
+        match unsafe { libc::sigwait(sigset.as_ref(), &mut sig) } {
+            0 => {
+                let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+                info.si_signo = sig;
+                return Ok(info);
+            }
+            libc::EINTR => continue,
+            err => return Err(nix::Error::Sys(Errno::from_i32(err))),
+        }
+    }
+}
+
+fn errno_of(e: &io::Error) -> Errno {
+    e.raw_os_error().map(Errno::from_i32).unwrap_or(Errno::EAGAIN)
+}
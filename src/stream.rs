@@ -0,0 +1,47 @@
+//! Receiving trapped signals as a `futures::Stream`
+//!
+//! `SignalStream` yields signals of a trap, so they can be awaited together
+//! with other futures (e.g. in `select!`). It's a thin wrapper around
+//! `AsyncTrap`, see the `async_trap` module for how the task is woken and
+//! the limitations.
+//!
+//! Only available on linux with the `futures` feature enabled.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use Signal;
+
+use async_trap::AsyncTrap;
+
+
+/// A stream of signals received by a trap
+///
+/// Created by `Trap::into_stream()`. The stream ends if the trap has no
+/// signals.
+#[derive(Debug)]
+pub struct SignalStream {
+    inner: AsyncTrap,
+}
+
+impl SignalStream {
+    pub(crate) fn new(inner: AsyncTrap) -> SignalStream {
+        SignalStream { inner }
+    }
+
+    /// Returns the underlying async trap
+    pub fn get_ref(&self) -> &AsyncTrap {
+        &self.inner
+    }
+}
+
+impl Stream for SignalStream {
+    type Item = Signal;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>)
+        -> Poll<Option<Signal>>
+    {
+        self.get_mut().inner.poll_recv(cx)
+    }
+}
@@ -0,0 +1,40 @@
+//! Adopting orphaned descendants (`PR_SET_CHILD_SUBREAPER`)
+//!
+//! When a process exits, its children are normally reparented to PID 1.
+//! A subreaper gets the orphaned descendants instead, so a supervisor that
+//! isn't PID 1 can still reap (and notice the exit of) a daemon that
+//! double-forks, or of the grandchildren left behind by a crashed child.
+//! The adopted processes are reported by `waitpid(-1)` like any other
+//! child, see `reaper::Reaper::adopt_orphans()`.
+//!
+//! The attribute is not inherited by children on `fork`, but survives
+//! `execve`. Only available on linux 3.4 and later.
+
+use libc::{self, c_int, c_ulong};
+use nix;
+
+
+/// Mark or unmark the calling process as a child subreaper
+pub fn set_child_subreaper(value: bool) -> nix::Result<()> {
+    let res = unsafe {
+        libc::prctl(libc::PR_SET_CHILD_SUBREAPER, value as c_ulong,
+            0 as c_ulong, 0 as c_ulong, 0 as c_ulong)
+    };
+    if res < 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(())
+}
+
+/// Returns `true` if the calling process is a child subreaper
+pub fn is_child_subreaper() -> nix::Result<bool> {
+    let mut value: c_int = 0;
+    let res = unsafe {
+        libc::prctl(libc::PR_GET_CHILD_SUBREAPER, &mut value as *mut c_int,
+            0 as c_ulong, 0 as c_ulong, 0 as c_ulong)
+    };
+    if res < 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(value != 0)
+}
@@ -0,0 +1,436 @@
+//! Supervising a set of child processes
+//!
+//! `Supervisor` is a library version of the `run` example: it spawns
+//! commands, reaps them on `SIGCHLD` and optionally restarts them when they
+//! exit, waiting between restarts according to a `backoff::Backoff`. On
+//! `SIGTERM` or `SIGINT` the signal is forwarded to all children, and those
+//! still running after the shutdown timeout are killed with `SIGKILL`.
+//! `run()` returns a `ShutdownSummary` describing why the supervisor
+//! stopped and how each process ended. The same shutdown sequence for
+//! arbitrary children is available as `terminate_children()`.
+//!
+//! Only available on linux.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use libc::pid_t;
+use nix;
+use nix::sys::signal::kill;
+use Signal;
+use Signal::{SIGCHLD, SIGINT, SIGKILL, SIGTERM};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+use backoff::Backoff;
+use children::Children;
+use ffi::io_error;
+use trap::Trap;
+
+
+/// When to restart a child process after it exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Never restart the process (the default)
+    Never,
+    /// Restart if the process exited with non-zero code or was killed
+    OnFailure,
+    /// Always restart the process
+    Always,
+}
+
+struct Process {
+    command: Command,
+    restart: RestartPolicy,
+    backoff: Backoff,
+    pid: Option<Pid>,
+    started: Option<Instant>,
+    /// Restarts since the process last ran for `reset_after`
+    failures: u32,
+    restart_at: Option<Instant>,
+    restarts: u32,
+    last_status: Option<WaitStatus>,
+    killed: bool,
+}
+
+/// Why `Supervisor::run()` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// All the processes exited and none of them had to be restarted
+    Completed,
+    /// The supervisor received the termination signal
+    Signal(Signal),
+}
+
+/// How a supervised process ended, part of `ShutdownSummary`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessSummary {
+    /// The program of the command
+    pub program: OsString,
+    /// Number of times the process was restarted
+    pub restarts: u32,
+    /// Status of the last exit, `None` if the process was never reaped
+    pub status: Option<WaitStatus>,
+    /// Whether the process was killed with `SIGKILL` after the shutdown
+    /// timeout
+    pub killed: bool,
+}
+
+/// Outcome of `Supervisor::run()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// Why the supervisor stopped
+    pub reason: ShutdownReason,
+    /// The processes in the order they were added
+    pub processes: Vec<ProcessSummary>,
+    /// How long the supervisor ran
+    pub uptime: Duration,
+}
+
+impl ShutdownSummary {
+    /// Returns `true` if all the processes exited with code zero
+    ///
+    /// Processes killed by a signal (including the forwarded termination
+    /// signal) count as failed.
+    pub fn is_success(&self) -> bool {
+        self.processes.iter()
+            .all(|p| matches!(p.status, Some(WaitStatus::Exited(_, 0))))
+    }
+}
+
+/// Spawns child processes and keeps them running
+///
+/// The supervisor is configured by chaining: `Supervisor::new().add(cmd)
+/// .restart_policy(RestartPolicy::Always).add(other_cmd).run()`.
+pub struct Supervisor {
+    processes: Vec<Process>,
+    shutdown_timeout: Duration,
+}
+
+impl Supervisor {
+    /// Create a supervisor without any processes
+    ///
+    /// Shutdown timeout is 10 seconds by default.
+    pub fn new() -> Supervisor {
+        Supervisor {
+            processes: Vec::new(),
+            shutdown_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Add a command to spawn
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, command: Command) -> Supervisor {
+        self.processes.push(Process {
+            command,
+            restart: RestartPolicy::Never,
+            backoff: Backoff::none(),
+            pid: None,
+            started: None,
+            failures: 0,
+            restart_at: None,
+            restarts: 0,
+            last_status: None,
+            killed: false,
+        });
+        self
+    }
+
+    /// Set the restart policy of the command added last
+    ///
+    /// # Panics
+    ///
+    /// Panics if no command was added yet.
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Supervisor {
+        self.processes.last_mut()
+            .expect("restart_policy() must be called after add()")
+            .restart = policy;
+        self
+    }
+
+    /// Set the delays between restarts of the command added last
+    ///
+    /// By default the process is restarted immediately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no command was added yet.
+    pub fn backoff(mut self, backoff: Backoff) -> Supervisor {
+        self.processes.last_mut()
+            .expect("backoff() must be called after add()")
+            .backoff = backoff;
+        self
+    }
+
+    /// Set how long to wait for children to exit after forwarding the
+    /// termination signal, before killing them with `SIGKILL`
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Supervisor {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Spawn the processes and supervise them until all of them exit
+    ///
+    /// Returns when there are no more children running: either all the
+    /// children exited and shouldn't be restarted, or the supervisor was
+    /// asked to shut down. If a command can't be spawned, the others are shut
+    /// down and the spawn error is returned, otherwise the summary of the
+    /// run.
+    ///
+    /// The signals are trapped in the calling thread, so they should be
+    /// blocked in all other threads (e.g. by creating the supervisor before
+    /// spawning any threads) to be received reliably.
+    pub fn run(mut self) -> io::Result<ShutdownSummary> {
+        let trap = Trap::trap(&[SIGCHLD, SIGTERM, SIGINT])?;
+        let started = Instant::now();
+        let mut state = State {
+            children: Children::new(),
+            shutdown: None,
+            killed: false,
+            error: None,
+            reason: ShutdownReason::Completed,
+        };
+        for idx in 0..self.processes.len() {
+            if !self.spawn(idx, &mut state) {
+                break;
+            }
+        }
+        while !state.children.is_empty() || self.restart_pending() {
+            let mut deadline = match state.shutdown {
+                Some(deadline) if !state.killed => deadline,
+                _ => Instant::now() + Duration::from_secs(3600),
+            };
+            deadline = self.processes.iter().filter_map(|p| p.restart_at)
+                .fold(deadline, |a, b| a.min(b));
+            match trap.wait(deadline) {
+                Some(SIGCHLD) => {
+                    let statuses = state.children.reap().map_err(io_error)?;
+                    for status in statuses {
+                        self.exited(status, &mut state);
+                    }
+                }
+                Some(sig) => {
+                    if state.shutdown.is_none() {
+                        state.reason = ShutdownReason::Signal(sig);
+                    }
+                    self.shut_down(sig, &mut state);
+                }
+                None if state.shutdown.is_some() && !state.killed &&
+                    Instant::now() >= deadline =>
+                {
+                    self.forward(SIGKILL);
+                    for process in &mut self.processes {
+                        process.killed = process.pid.is_some();
+                    }
+                    state.killed = true;
+                }
+                None => {}
+            }
+            self.restart_due(&mut state);
+        }
+        if let Some(e) = state.error {
+            return Err(e);
+        }
+        Ok(ShutdownSummary {
+            reason: state.reason,
+            processes: self.processes.iter().map(|p| ProcessSummary {
+                program: p.command.get_program().to_os_string(),
+                restarts: p.restarts,
+                status: p.last_status,
+                killed: p.killed,
+            }).collect(),
+            uptime: started.elapsed(),
+        })
+    }
+
+    /// Spawn the process, returns `false` if spawning failed
+    fn spawn(&mut self, idx: usize, state: &mut State) -> bool {
+        let process = &mut self.processes[idx];
+        // children are reaped via `Children` on SIGCHLD
+        #[allow(clippy::zombie_processes)]
+        match process.command.spawn() {
+            Ok(child) => {
+                let pid = Pid::from_raw(child.id() as pid_t);
+                process.pid = Some(pid);
+                process.started = Some(Instant::now());
+                state.children.watch_child(pid);
+                true
+            }
+            Err(e) => {
+                state.error = Some(e);
+                self.shut_down(SIGTERM, state);
+                false
+            }
+        }
+    }
+
+    fn exited(&mut self, status: WaitStatus, state: &mut State) {
+        let pid = status.pid();
+        let idx = match self.processes.iter().position(|p| p.pid == pid) {
+            Some(idx) => idx,
+            None => return,
+        };
+        self.processes[idx].pid = None;
+        self.processes[idx].last_status = Some(status);
+        if state.shutdown.is_some() {
+            return;
+        }
+        let restart = match self.processes[idx].restart {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => {
+                !matches!(status, WaitStatus::Exited(_, 0))
+            }
+            RestartPolicy::Always => true,
+        };
+        if restart {
+            let process = &mut self.processes[idx];
+            let healthy = match (process.backoff.reset_threshold(),
+                                 process.started)
+            {
+                (Some(reset), Some(started)) => started.elapsed() >= reset,
+                _ => false,
+            };
+            if healthy {
+                process.failures = 0;
+            }
+            let delay = process.backoff.delay(process.failures);
+            process.failures = process.failures.saturating_add(1);
+            process.restart_at = Some(Instant::now() + delay);
+        }
+    }
+
+    fn restart_pending(&self) -> bool {
+        self.processes.iter().any(|p| p.restart_at.is_some())
+    }
+
+    fn restart_due(&mut self, state: &mut State) {
+        let now = Instant::now();
+        for idx in 0..self.processes.len() {
+            match self.processes[idx].restart_at {
+                Some(at) if at <= now && state.shutdown.is_none() => {
+                    self.processes[idx].restart_at = None;
+                    self.processes[idx].restarts += 1;
+                    if !self.spawn(idx, state) {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn shut_down(&mut self, sig: Signal, state: &mut State) {
+        // pending restarts are cancelled
+        for process in &mut self.processes {
+            process.restart_at = None;
+        }
+        self.forward(sig);
+        if state.shutdown.is_none() {
+            state.shutdown = Some(Instant::now() + self.shutdown_timeout);
+        }
+    }
+
+    fn forward(&self, sig: Signal) {
+        for pid in self.processes.iter().filter_map(|p| p.pid) {
+            // errors are ignored: either the child is already dead but not
+            // reaped yet (`ESRCH`) or nothing useful can be done anyway
+            kill(pid, Some(sig.into())).ok();
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Supervisor {
+        Supervisor::new()
+    }
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("processes", &self.processes.len())
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish()
+    }
+}
+
+struct State {
+    children: Children,
+    /// Deadline for children to exit after the termination signal
+    shutdown: Option<Instant>,
+    /// Whether children were killed by `SIGKILL` after the deadline
+    killed: bool,
+    error: Option<io::Error>,
+    reason: ShutdownReason,
+}
+
+/// Outcome of `terminate_children()`
+#[derive(Debug, Clone, Default)]
+pub struct TerminationReport {
+    /// Children that exited after `SIGTERM` within the grace period
+    ///
+    /// This includes children that were already dead or reaped by someone
+    /// else.
+    pub graceful: Vec<pid_t>,
+    /// Children that were still running after the grace period and were
+    /// killed with `SIGKILL`
+    pub killed: Vec<pid_t>,
+}
+
+/// Terminate child processes, killing them if they don't exit in time
+///
+/// Sends `SIGTERM` to each child, waits up to `grace` for them to exit,
+/// then sends `SIGKILL` to the survivors. All the children are reaped
+/// before returning, so no zombies are left.
+///
+/// `SIGCHLD` is trapped in the calling thread while waiting, so it should
+/// be blocked in all other threads. Note that `SIGCHLD` signals received
+/// here are consumed, so other code waiting for them may miss the
+/// notification (though these children are reaped anyway).
+pub fn terminate_children(pids: &[pid_t], grace: Duration)
+    -> nix::Result<TerminationReport>
+{
+    // Trap before sending signals, so that no `SIGCHLD` is lost
+    let trap = Trap::trap(&[SIGCHLD])?;
+    let mut children = Children::new();
+    for &pid in pids {
+        children.watch_child(Pid::from_raw(pid));
+    }
+    let mut report = TerminationReport::default();
+    forward_raw(pids, SIGTERM);
+    let deadline = Instant::now() + grace;
+    loop {
+        children.reap()?;
+        if children.is_empty() || trap.wait(deadline).is_none() {
+            break;
+        }
+    }
+    let survivors = pids.iter().cloned()
+        .filter(|&pid| children.is_watched(Pid::from_raw(pid)))
+        .collect::<Vec<_>>();
+    report.graceful = pids.iter().cloned()
+        .filter(|pid| !survivors.contains(pid))
+        .collect();
+    forward_raw(&survivors, SIGKILL);
+    loop {
+        // multiple deaths may be coalesced into a single `SIGCHLD`, so
+        // all the children are checked when any signal arrives
+        children.reap()?;
+        if children.is_empty() {
+            break;
+        }
+        trap.wait(Instant::now() + Duration::from_secs(3600));
+    }
+    report.killed = survivors;
+    Ok(report)
+}
+
+fn forward_raw(pids: &[pid_t], sig: Signal) {
+    for &pid in pids {
+        // the child may be dead but not reaped yet
+        kill(Pid::from_raw(pid), Some(sig.into())).ok();
+    }
+}
@@ -0,0 +1,119 @@
+//! Awaiting signals in a tokio runtime
+//!
+//! `TokioSignalStream` registers a `SignalFd` in the tokio reactor with
+//! `AsyncFd`, so receiving a signal doesn't need a helper thread (unlike
+//! `async_trap`), and any signal directed to the process wakes the task.
+//! Compared to `tokio::signal`, the signals are masked out and received
+//! with their siginfo, the same way `Trap` does.
+//!
+//! As with `Trap`, the signals must be blocked in all the threads: create
+//! the `SignalFd` before the runtime is started, so its worker threads
+//! inherit the mask, and wrap it with `TokioSignalStream::from_signal_fd()`
+//! inside the runtime. The stream must be dropped in the thread where the
+//! `SignalFd` was created, so use it on a current-thread runtime (or in a
+//! `LocalSet`).
+//!
+//! Only available on linux with the `tokio` feature enabled.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio_crate::io::unix::AsyncFd;
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+
+use ffi::io_error;
+use siginfo::SigInfo;
+use signalfd::SignalFd;
+use Signal;
+
+
+/// A stream of signals registered in the tokio reactor
+///
+/// Unlike `stream::SignalStream`, it yields the siginfo of every signal.
+#[derive(Debug)]
+pub struct TokioSignalStream {
+    inner: AsyncFd<SignalFd>,
+}
+
+impl TokioSignalStream {
+    /// Mask out the signals and register the descriptor receiving them
+    ///
+    /// Must be called within a tokio runtime, see the module docs for why
+    /// `from_signal_fd()` is usually preferable.
+    pub fn new(signals: &[Signal]) -> io::Result<TokioSignalStream> {
+        let signal_fd = SignalFd::new(signals).map_err(io_error)?;
+        TokioSignalStream::from_signal_fd(signal_fd)
+    }
+
+    /// Register the already created `SignalFd` in the tokio reactor
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn from_signal_fd(signal_fd: SignalFd)
+        -> io::Result<TokioSignalStream>
+    {
+        Ok(TokioSignalStream {
+            inner: AsyncFd::new(signal_fd)?,
+        })
+    }
+
+    /// Returns the underlying `SignalFd`
+    pub fn get_ref(&self) -> &SignalFd {
+        self.inner.get_ref()
+    }
+
+    /// Wait for the next signal
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv { stream: self }
+    }
+
+    /// Poll for the next signal
+    ///
+    /// If no signal is pending, the task is woken when one arrives.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>)
+        -> Poll<io::Result<SigInfo>>
+    {
+        loop {
+            let mut guard = match self.inner.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            // the readiness is cleared when no signal is left
+            match guard.try_io(|inner| inner.get_ref().try_recv()) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Future returned by `TokioSignalStream::recv()`
+#[derive(Debug)]
+pub struct Recv<'a> {
+    stream: &'a mut TokioSignalStream,
+}
+
+impl<'a> Future for Recv<'a> {
+    type Output = io::Result<SigInfo>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>)
+        -> Poll<io::Result<SigInfo>>
+    {
+        self.stream.poll_recv(cx)
+    }
+}
+
+/// The stream never ends, read errors are yielded as items
+#[cfg(feature = "futures")]
+impl Stream for TokioSignalStream {
+    type Item = io::Result<SigInfo>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>)
+        -> Poll<Option<io::Result<SigInfo>>>
+    {
+        self.get_mut().poll_recv(cx).map(Some)
+    }
+}
@@ -9,134 +9,2452 @@
 //!
 //! Especially useful for running (multiple) child processes simultaneously.
 
+use std::collections::VecDeque;
 use std::fmt;
-use std::mem::uninitialized;
+use std::mem;
+use std::os::unix::io::RawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::ptr::null;
+#[cfg(target_os = "linux")]
 use std::ptr::null_mut;
+use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+#[cfg(target_os = "linux")]
+use std::sync::{Arc, MutexGuard};
 
-use std::time::{Instant, Duration};
-use nix::sys::signal::{sigaction, SigAction, Signal, SigSet, SaFlags};
+use std::time::{Instant, Duration, SystemTime};
+use nix;
+use nix::sys::signal::{sigaction, SigAction, SigSet, SaFlags};
 use nix::sys::signal::{pthread_sigmask, SigmaskHow, SigHandler};
-use nix::errno::{Errno, errno};
-use libc::{self, timespec, sigwait};
+use nix::errno::Errno;
+use nix::unistd;
+use nix::sys::select::{pselect, FdSet};
+use nix::sys::time::{TimeSpec, TimeValLike};
+use libc::{self, sigwait};
+#[cfg(target_os = "linux")]
+use libc::timespec;
+
+use {to_raw, from_raw, check_catchable, Error, Signal};
+use children::{reap_any, ChildEvent};
+use events::Events;
+#[cfg(target_os = "linux")]
+use nix::unistd::Pid;
+#[cfg(target_os = "linux")]
+use pidfd::PidFd;
+
+use nix::poll::{PollFd, PollFlags};
+#[cfg(target_os = "linux")]
+use nix::sys::signalfd::{signalfd, SfdFlags, SIGNALFD_NEW};
+#[cfg(target_os = "linux")]
+use ffi::read_signalfd;
+use ffi::wait_readable;
+#[cfg(target_os = "linux")]
+use ffi::io_error;
+#[cfg(target_os = "linux")]
+use siginfo::SigInfo;
+#[cfg(target_os = "linux")]
+use async_trap::AsyncTrap;
+#[cfg(all(target_os = "linux", feature = "futures"))]
+use stream::SignalStream;
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(all(target_os = "linux", feature = "mio"))]
+use mio::{event, unix::SourceFd, Interest, Registry, Token};
+#[cfg(any(target_os = "macos", target_os = "ios",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+use kqueue::SignalQueue;
+use sigwait_thread::SigwaitThread;
 
 /// A RAII guard for masking out signals and waiting for them synchronously
 ///
 /// Trap temporarily replaces signal handlers to an empty handler, effectively
 /// activating singnals that are ignored by default.
 ///
-/// Old signal handlers are restored in `Drop` handler.
+/// Old signal handlers are restored in `Drop` handler. Overlapping traps
+/// for the same signal share the handler: the original disposition is
+/// restored when the last of them is dropped, regardless of the order.
+///
+/// Only signals that were blocked by the trap itself are unblocked on drop.
+/// Signals of the set that were already blocked when the trap was created
+/// stay blocked, and changes made to the mask for other signals while the
+/// trap is active are preserved.
+///
+/// Signals that arrived while trapped but weren't consumed by `wait()` or
+/// `next()` are still pending when the trap is dropped. Handlers are
+/// restored first and then the signal mask, so such signals are delivered
+/// with the restored (usually default) disposition right in the `drop()`.
+/// E.g. a pending `SIGTERM` kills the process at this point. Use
+/// `drain_before_drop()` if that is not desired.
+///
+/// The signal mask is per-thread, so the trap must be dropped in the thread
+/// where it was created. If it's dropped in another thread, the handlers
+/// are still restored, but the signals are unblocked in the dropping
+/// thread, and the original thread keeps them blocked. Debug builds print
+/// a warning to stderr in that case (the drop doesn't panic, as it may run
+/// during unwinding).
+///
+/// Own the trap on the stack of the thread's main function (or `main()`
+/// itself) rather than putting it into a thread-local: thread-local
+/// destructors run in unspecified order, so other thread-locals may already
+/// be destroyed when the trap restores handlers and pending signals are
+/// delivered (e.g. a logger used by the restored handler).
 pub struct Trap {
-    oldset: SigSet,
-    oldsigs: Vec<(Signal, SigAction)>,
+    blocked: Option<SigSet>,
+    oldsigs: Vec<(Signal, OldAction)>,
     sigset: SigSet,
+    drain: bool,
+    reraise: bool,
+    fd: Option<RawFd>,
+    // `pthread_t` is a pointer on some systems, which would make `Trap` !Send
+    thread: usize,
+    coalesce: bool,
+    unacked: AtomicBool,
+    // indexed by signal number
+    received: Vec<AtomicU64>,
+    helper: OnceLock<SigwaitThread>,
+    reap_children: bool,
+    reaped: VecDeque<ChildEvent>,
+    #[cfg(target_os = "linux")]
+    pidfds: Vec<PidFd>,
+}
+
+/// A point in time until which `Trap` waits for signals
+///
+/// This is used instead of a bare `Instant` to make it clear at the call
+/// site whether the value is a deadline or a timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Deadline at the specified instant
+    pub fn at(instant: Instant) -> Deadline {
+        Deadline(instant)
+    }
+
+    /// Deadline after the timeout passes, counting from now
+    pub fn after(timeout: Duration) -> Deadline {
+        Deadline(Instant::now() + timeout)
+    }
+
+    /// Returns instant of the deadline
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+}
+
+/// Outcome of `Trap::wait_detailed()`
+#[derive(Debug, Clone, Copy)]
+pub struct WaitDetail {
+    /// Signal received or `None` if deadline has been reached
+    pub signal: Option<Signal>,
+    /// Number of times the wait was interrupted by `EINTR` and restarted
+    pub interruptions: usize,
+    /// Whether a blocking wait actually happened
+    ///
+    /// This is `false` if the deadline had already passed when `wait` was
+    /// called, so only a zero-timeout poll for pending signals was done.
+    pub waited: bool,
+}
+
+/// A signal with the time it was received, returned by `Trap::wait_timed()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedSignal {
+    /// The signal received
+    pub signal: Signal,
+    /// When the wait returned the signal
+    ///
+    /// Use it to measure latency (e.g. from `SIGTERM` to the end of the
+    /// shutdown), it's not affected by changes of the system clock.
+    pub instant: Instant,
+    /// Wall-clock time taken at the same moment, e.g. for logging
+    pub system_time: SystemTime,
+}
+
+/// Options for `Trap::with_handler_options`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HandlerOptions {
+    one_shot: bool,
+}
+
+impl HandlerOptions {
+    /// Default options
+    pub fn new() -> HandlerOptions {
+        HandlerOptions::default()
+    }
+
+    /// Reset the signal disposition to default when the handler fires
+    ///
+    /// This sets `SA_RESETHAND`, so the handler runs for the first signal
+    /// only and the default action happens for the next one. Useful for
+    /// the "try to recover once, then crash normally" pattern.
+    pub fn one_shot(mut self, value: bool) -> HandlerOptions {
+        self.one_shot = value;
+        self
+    }
+}
+
+/// How a trap receives signals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// `sigtimedwait()`-like calls (the default)
+    #[default]
+    Sigwait,
+    /// A `signalfd`, like for `Trap::trap_via_signalfd()`
+    #[cfg(target_os = "linux")]
+    Signalfd,
+    /// A helper thread taking the signals with `sigwait()`
+    ///
+    /// This works on any unix system, and it's used by `wait()` on systems
+    /// that have neither `sigtimedwait` nor `kqueue`. The thread is spawned
+    /// by `build()` and inherits the signal mask of the trap. It takes the
+    /// signals as soon as they arrive and passes them to the trap through a
+    /// pipe (see `poll_fd()`), so `pending()` doesn't report them, and the
+    /// signals sent to the thread of the trap specifically (e.g. by
+    /// `raise()`) are never received.
+    Thread,
+}
+
+/// Builder for a `Trap` with non-default settings
+///
+/// Created by `Trap::builder()`, e.g.
+/// `Trap::builder().signals(&[SIGTERM, SIGINT]).restart(true).build()`.
+/// Unlike `Trap::trap()`, the builder never panics: `build()` returns all
+/// the errors (in the same way as `Trap::trap_checked()`), so the caller
+/// decides whether they are fatal.
+#[derive(Debug, Clone, Default)]
+pub struct TrapBuilder {
+    signals: Vec<Signal>,
+    restart: bool,
+    backend: Backend,
+    keep_dispositions: bool,
+    reap_children: bool,
+}
+
+impl TrapBuilder {
+    /// Add a signal to trap
+    pub fn signal(mut self, signal: Signal) -> TrapBuilder {
+        if !self.signals.contains(&signal) {
+            self.signals.push(signal);
+        }
+        self
+    }
+
+    /// Add signals to trap
+    pub fn signals(mut self, signals: &[Signal]) -> TrapBuilder {
+        for &sig in signals {
+            self = self.signal(sig);
+        }
+        self
+    }
+
+    /// Set `SA_RESTART` on the empty handler installed by the trap
+    ///
+    /// The handler only runs if a trapped signal is delivered while it's not
+    /// blocked (e.g. in another thread, or by `wait_select()`). With this
+    /// flag, interrupted system calls are restarted instead of failing with
+    /// `EINTR`. Note that `wait_select()` is restarted too (and doesn't
+    /// return the signal until `pselect` returns for other reasons), so
+    /// don't use both.
+    pub fn restart(mut self, value: bool) -> TrapBuilder {
+        self.restart = value;
+        self
+    }
+
+    /// Choose how the trap receives signals
+    pub fn backend(mut self, backend: Backend) -> TrapBuilder {
+        self.backend = backend;
+        self
+    }
+
+    /// Whether to install the empty handler for the signals (the default)
+    ///
+    /// The handler makes ignored signals (either explicitly, or by default
+    /// like `SIGCHLD`) pending, so they can be waited for. When set to
+    /// `false`, dispositions are kept intact and only the mask is changed.
+    /// Whether ignored signals can be waited for then depends on the system
+    /// (linux keeps blocked signals pending even if they are ignored).
+    pub fn unignore(mut self, value: bool) -> TrapBuilder {
+        self.keep_dispositions = !value;
+        self
+    }
+
+    /// Reap exited children when `SIGCHLD` arrives
+    ///
+    /// This adds `SIGCHLD` to the trapped signals. On `SIGCHLD`,
+    /// `next_event()` and `wait_event()` run `children::reap_any()` and
+    /// return a `TrapEvent::Child` for each reaped child instead of the
+    /// signal. Other methods still return bare `SIGCHLD`.
+    pub fn reap_children(mut self, value: bool) -> TrapBuilder {
+        self.reap_children = value;
+        self
+    }
+
+    /// Create the trap
+    pub fn build(mut self) -> Result<Trap, Error> {
+        if self.reap_children {
+            self = self.signal(Signal::SIGCHLD);
+        }
+        let flags = if self.restart {
+            SaFlags::SA_RESTART
+        } else {
+            SaFlags::empty()
+        };
+        #[allow(unused_mut)]
+        let mut trap = Trap::create(&self.signals, flags,
+                                    !self.keep_dispositions)?;
+        trap.reap_children = self.reap_children;
+        match self.backend {
+            Backend::Sigwait => {}
+            Backend::Thread => {
+                trap.start_helper().map_err(Error::wait)?;
+            }
+            #[cfg(target_os = "linux")]
+            Backend::Signalfd => {
+                let flags = SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC;
+                let fd = signalfd(SIGNALFD_NEW, &trap.sigset, flags)
+                    .map_err(Error::signalfd)?;
+                trap.fd = Some(fd);
+            }
+        }
+        Ok(trap)
+    }
+}
+
+/// Outcome of `Trap::wait_select()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selected {
+    /// A trapped signal arrived, the descriptor sets are not changed
+    Signal(Signal),
+    /// Number of ready descriptors, the sets contain only ready ones
+    Ready(usize),
+    /// The deadline has been reached
+    Timeout,
+}
+
+/// A signal or a reaped child, returned by `Trap::next_event()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapEvent {
+    /// A trapped signal arrived
+    Signal(Signal),
+    /// A child exited and was reaped
+    ///
+    /// See `TrapBuilder::reap_children()` and `Trap::watch_pidfd()`.
+    Child(ChildEvent),
+}
+
+/// Numbers of signals received by a trap
+///
+/// Returned by `Trap::stats()`. It's a snapshot, it isn't updated when more
+/// signals arrive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrapStats {
+    counts: Vec<(Signal, u64)>,
+}
+
+impl TrapStats {
+    /// Returns how many times the signal was received
+    pub fn count(&self, signal: Signal) -> u64 {
+        self.counts.iter()
+            .find(|&&(sig, _)| sig == signal)
+            .map(|&(_, count)| count)
+            .unwrap_or(0)
+    }
+
+    /// Returns the total number of signals received
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// Iterate over signals and their counts, ordered by signal number
+    ///
+    /// This includes all the signals of the trap, even if none of them was
+    /// received yet, and signals removed from the trap that were received
+    /// before.
+    pub fn iter(&self) -> impl Iterator<Item=(Signal, u64)> + '_ {
+        self.counts.iter().cloned()
+    }
+}
+
+fn new_counters() -> Vec<AtomicU64> {
+    let max = Signal::iterator().map(to_raw).max().unwrap_or(0);
+    (0..=max).map(|_| AtomicU64::new(0)).collect()
+}
+
+/// Bitmask of signals delivered to `empty_handler`, used by `wait_select()`
+static DELIVERED: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn empty_handler(sig: libc::c_int) {
+    if sig > 0 && sig < 64 {
+        DELIVERED.fetch_or(1 << sig, Ordering::SeqCst);
+    }
+}
+
+/// Take one of the signals in `sigset` recorded by `empty_handler`
+fn take_delivered(sigset: &SigSet) -> Option<Signal> {
+    let sig = Signal::iterator().find(|&sig| {
+        sigset.contains(sig.into()) &&
+            DELIVERED.load(Ordering::SeqCst) & (1 << to_raw(sig)) != 0
+    })?;
+    DELIVERED.fetch_and(!(1 << to_raw(sig)), Ordering::SeqCst);
+    Some(sig)
+}
+
+/// Forget the signals in `sigset` recorded by `empty_handler` so far
+fn clear_delivered(sigset: &SigSet) {
+    let mask = Signal::iterator()
+        .filter(|&sig| sigset.contains(sig.into()))
+        .fold(0u64, |mask, sig| mask | 1 << to_raw(sig));
+    DELIVERED.fetch_and(!mask, Ordering::SeqCst);
 }
 
-extern "C" fn empty_handler(_: libc::c_int) { }
+/// Returns `true` if the disposition of the signal is `empty_handler`
+fn has_empty_handler(sig: Signal) -> bool {
+    unsafe {
+        let mut current: libc::sigaction = mem::zeroed();
+        libc::sigaction(to_raw(sig), null(), &mut current) == 0 &&
+            current.sa_sigaction ==
+                empty_handler as extern "C" fn(libc::c_int) as usize
+    }
+}
 
 impl Trap {
     /// Create and activate the signal trap for specified signals. Signals not
     /// in list will be delivered asynchronously as always.
-    pub fn trap(signals: &[Signal]) -> Trap {
-        unsafe {
-            let mut sigset = SigSet::empty();
-            for &sig in signals {
-                sigset.add(sig);
-            }
-            let mut oldset = uninitialized();
-            let mut oldsigs = Vec::new();
-            pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&sigset), Some(&mut oldset))
-                .unwrap();
-            // Set signal handlers to an empty function, this allows ignored
-            // signals to become pending, effectively allowing them to be
-            // waited for.
-            for &sig in signals {
-                oldsigs.push((sig, sigaction(sig,
+    ///
+    /// Signals are blocked before any disposition is changed, and the
+    /// handlers are only installed if blocking succeeded. So a signal that
+    /// arrives during construction stays pending and is returned by
+    /// `wait()`/`next()` later, instead of running its default action. This
+    /// is important for job-control signals (`SIGTSTP`, `SIGTTIN`,
+    /// `SIGTTOU`) whose default action stops the process.
+    ///
+    /// Note that while the trap is active, job-control signals don't stop
+    /// the process anymore, it's up to the caller to act on them.
+    ///
+    /// Returns `Error::Uncatchable` if the list contains signals that can't
+    /// be caught (see `uncatchable_signals()`), before anything is changed.
+    /// If construction fails midway, the mask and dispositions are restored.
+    #[allow(clippy::self_named_constructors)]
+    pub fn trap(signals: &[Signal]) -> Result<Trap, Error> {
+        Trap::create(signals, SaFlags::empty(), true)
+    }
+
+    /// Create the signal trap for the signals in the set
+    ///
+    /// Same as `trap()` but accepts a set, e.g. one built by `sigset!`.
+    pub fn from_sigset(sigset: &SigSet) -> Result<Trap, Error> {
+        let signals = Signal::iterator()
+            .filter(|&sig| sigset.contains(sig.into()))
+            .collect::<Vec<_>>();
+        Trap::trap(&signals)
+    }
+
+    /// Create the signal trap, verifying that default actions of the signals
+    /// can't fire while the trap is being set up
+    ///
+    /// This is the same as `trap()`, which used to panic on errors.
+    pub fn trap_checked(signals: &[Signal]) -> Result<Trap, Error> {
+        Trap::trap(signals)
+    }
+
+    /// A trap for the signals that hasn't changed anything yet
+    fn new(sigset: SigSet, blocked: Option<SigSet>) -> Trap {
+        Trap {
+            blocked,
+            oldsigs: Vec::new(),
+            sigset,
+            drain: false,
+            reraise: false,
+            fd: None,
+            thread: current_thread(),
+            coalesce: false,
+            unacked: AtomicBool::new(false),
+            received: new_counters(),
+            helper: OnceLock::new(),
+            reap_children: false,
+            reaped: VecDeque::new(),
+            #[cfg(target_os = "linux")]
+            pidfds: Vec::new(),
+        }
+    }
+
+    /// Implementation of `trap()` and `TrapBuilder::build()`
+    fn create(signals: &[Signal], flags: SaFlags, unignore: bool)
+        -> Result<Trap, Error>
+    {
+        check_catchable(signals)?;
+        let mut sigset = SigSet::empty();
+        for &sig in signals {
+            sigset.add(sig.into());
+        }
+        let mut oldset = SigSet::empty();
+        pthread_sigmask(SigmaskHow::SIG_BLOCK,
+                        Some(&sigset), Some(&mut oldset))
+            .map_err(Error::sigmask)?;
+        // From now on dropping the trap restores everything changed so far
+        let mut trap = Trap::new(sigset,
+            Some(newly_blocked(&sigset, &oldset)));
+        if !unignore {
+            return Ok(trap);
+        }
+        for &sig in signals {
+            let old = unsafe {
+                install_shared(sig,
                     &SigAction::new(SigHandler::Handler(empty_handler),
-                        SaFlags::empty(), sigset))
-                    .unwrap()));
+                        flags, sigset))
+                    .map_err(|e| Error::sigaction(sig, e))?
+            };
+            trap.oldsigs.push((sig, old));
+        }
+        Ok(trap)
+    }
+
+    /// Run the closure with the signals trapped
+    ///
+    /// This is the same as the `with_trap()` function: handlers and mask
+    /// are restored when the closure returns and also if it panics, and
+    /// signals that weren't consumed by the closure are discarded.
+    pub fn with<T, F>(signals: &[Signal], func: F) -> Result<T, Error>
+        where F: FnOnce(&mut Trap) -> T
+    {
+        with_trap(signals, func)
+    }
+
+    /// Temporarily restore the original dispositions and mask
+    ///
+    /// While the returned guard exists, the signals are handled as if there
+    /// is no trap (e.g. while running third-party code that needs the
+    /// default `SIGCHLD` handling). The trap is re-armed when the guard is
+    /// dropped.
+    ///
+    /// Like on drop, pending signals are delivered right away with the
+    /// restored dispositions, use `drain()` before suspending if that's not
+    /// desired. If other traps share the handler for a signal, it stays
+    /// installed until they are dropped or suspended too.
+    pub fn suspend(&mut self) -> Suspended<'_> {
+        let helper = self.helper.get().is_some();
+        self.release_helper();
+        let mut handlers = Vec::with_capacity(self.oldsigs.len());
+        unsafe {
+            for &(sig, ref old) in &self.oldsigs {
+                handlers.push(match *old {
+                    OldAction::Own(ref sigact) => {
+                        Some(sigaction(sig.into(), sigact).unwrap())
+                    }
+                    OldAction::Shared => release_shared(sig),
+                });
             }
-            Trap {
-                oldset: oldset,
-                oldsigs: oldsigs,
-                sigset: sigset,
+        }
+        if let Some(ref blocked) = self.blocked {
+            if !is_empty_set(blocked) {
+                pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(blocked), None)
+                    .unwrap();
             }
         }
+        Suspended { trap: self, handlers, helper }
     }
 
-    /// Wait until any of signals arrived or timeout occurs. In case of
-    /// timeout returns None, otherwise returns signal number.
+    /// Returns a builder to create a trap with non-default settings
+    pub fn builder() -> TrapBuilder {
+        TrapBuilder::default()
+    }
+
+    /// Create the signal trap that receives signals via `signalfd`
     ///
-    /// Note the argument here is a deadline, not timeout. It's easier to work
-    /// with deadline if you call wait() function in a loop.
+    /// Signals are masked out and handlers are set the same way as for
+    /// `trap()`, and all the waiting methods work the same way, but signals
+    /// are read from a file descriptor instead of `sigwait`-like calls. The
+    /// descriptor is closed on drop.
     #[cfg(target_os = "linux")]
-    pub fn wait(&self, deadline: Instant) -> Option<Signal> {
-        use libc::sigtimedwait;
+    pub fn trap_via_signalfd(signals: &[Signal]) -> Result<Trap, Error> {
+        let mut trap = Trap::trap(signals)?;
+        let fd = signalfd(SIGNALFD_NEW, &trap.sigset,
+                          SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+            .map_err(Error::signalfd)?;
+        trap.fd = Some(fd);
+        Ok(trap)
+    }
 
-        loop {
-            let now = Instant::now();
-            let timeout = if deadline > now {
-                deadline.duration_since(now)
-            } else {
-                Duration::from_secs(0)
-            };
-            let tm = timespec {
-                tv_sec: timeout.as_secs() as libc::time_t,
-                tv_nsec: (timeout - Duration::from_secs(timeout.as_secs()))
-                         .subsec_nanos() as libc::c_long,
+    /// Block until any of signals arrived
+    ///
+    /// Same as `next()` but returns errors instead of panicking. `Ok(None)`
+    /// means the trap has no signals, so the iteration is over.
+    pub fn try_next(&mut self) -> Result<Option<Signal>, Error> {
+        if self.is_empty() {
+            // nothing could ever arrive, and `sigwait` would fail
+            return Ok(None);
+        }
+        if let Some(helper) = self.helper.get() {
+            let mut detail = WaitDetail {
+                signal: None,
+                interruptions: 0,
+                waited: false,
             };
-            let sig = unsafe { sigtimedwait(self.sigset.as_ref(),
-                                            null_mut(), &tm) };
-            if sig > 0 {
-                return Some(Signal::from_c_int(sig).unwrap());
-            } else {
-                match Errno::last() {
-                    Errno::EAGAIN => {
-                        return None;
-                    }
-                    Errno::EINTR => {
-                        continue;
-                    }
-                    _ => {
-                        panic!("Sigwait error: {}", errno());
-                    }
+            self.wait_helper(helper, &mut detail, None)
+                .map_err(Error::wait)?;
+            return Ok(detail.signal);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(fd) = self.fd {
+                let info = self.read_fd_blocking(fd).map_err(Error::wait)?;
+                let sig = info.ssi_signo as libc::c_int;
+                return from_raw(sig).map(|sig| Some(self.received(sig)))
+                    .ok_or(Error::InvalidSignal(sig));
+            }
+        }
+        let mut sig: libc::c_int = 0;
+        loop {
+            match unsafe { sigwait(self.sigset.as_ref(), &mut sig) } {
+                0 => {
+                    return from_raw(sig).map(|sig| Some(self.received(sig)))
+                        .ok_or(Error::InvalidSignal(sig));
                 }
+                libc::EINTR => continue,
+                err => return Err(Error::Wait(Errno::from_i32(err))),
             }
         }
     }
-}
 
-impl Iterator for Trap {
-    type Item = Signal;
-    fn next(&mut self) -> Option<Signal> {
-        let mut sig: libc::c_int = 0;
+    /// Block until a signal arrives or a child is reaped
+    ///
+    /// Same as `next()`, but for a trap created with
+    /// `TrapBuilder::reap_children()` a `SIGCHLD` is replaced by the
+    /// children it reaped. A `SIGCHLD` for children reaped elsewhere is
+    /// skipped. The children registered with `watch_pidfd()` are reported
+    /// too. Returns `Ok(None)` if the trap has neither signals nor pidfds.
+    pub fn next_event(&mut self) -> Result<Option<TrapEvent>, Error> {
+        self.poll_event(None)
+    }
+
+    /// Same as `next_event()` but returns `Ok(None)` on deadline
+    pub fn wait_event(&mut self, deadline: Instant)
+        -> Result<Option<TrapEvent>, Error>
+    {
+        self.poll_event(Some(deadline))
+    }
+
+    fn poll_event(&mut self, deadline: Option<Instant>)
+        -> Result<Option<TrapEvent>, Error>
+    {
         loop {
-            if unsafe { sigwait(self.sigset.as_ref(), &mut sig) } == 0 {
-                return Some(Signal::from_c_int(sig).unwrap());
-            } else {
-                if Errno::last() == Errno::EINTR {
+            if let Some(child) = self.reaped.pop_front() {
+                return Ok(Some(TrapEvent::Child(child)));
+            }
+            #[cfg(target_os = "linux")]
+            {
+                if !self.pidfds.is_empty() {
+                    match self.wait_pidfds(deadline)? {
+                        Selected::Signal(sig) => {
+                            if let Some(event) = self.event(sig) {
+                                return Ok(Some(event));
+                            }
+                        }
+                        Selected::Ready(_) => {}
+                        Selected::Timeout if deadline.is_some() => {
+                            return Ok(None);
+                        }
+                        Selected::Timeout => {}
+                    }
                     continue;
                 }
-                panic!("Sigwait error: {}", errno());
+            }
+            let sig = match deadline {
+                Some(deadline) => self.wait_result(deadline)?,
+                None => self.try_next()?,
+            };
+            let sig = match sig {
+                Some(sig) => sig,
+                None => return Ok(None),
+            };
+            if let Some(event) = self.event(sig) {
+                return Ok(Some(event));
             }
         }
     }
-}
 
-impl Drop for Trap {
-    fn drop(&mut self) {
-        unsafe {
-            for &(sig, ref sigact) in self.oldsigs.iter() {
-                sigaction(sig, sigact).unwrap();
+    /// Report the exit of a child by `next_event()`, without `SIGCHLD`
+    ///
+    /// While there are watched pidfds, `next_event()` and `wait_event()`
+    /// wait for the descriptors and for the signals with `wait_select()`,
+    /// so the same limitations apply (descriptors must be below
+    /// `FD_SETSIZE`). When the child exits it's reaped, a `TrapEvent::Child`
+    /// is returned and the pidfd is closed. The process must be a child of
+    /// the calling process.
+    #[cfg(target_os = "linux")]
+    pub fn watch_pidfd(&mut self, pidfd: PidFd) {
+        self.pidfds.push(pidfd);
+    }
+
+    /// Stop watching the child, returns its descriptor
+    #[cfg(target_os = "linux")]
+    pub fn unwatch_pidfd(&mut self, pid: Pid) -> Option<PidFd> {
+        let idx = self.pidfds.iter().position(|pidfd| pidfd.pid() == pid)?;
+        Some(self.pidfds.remove(idx))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn wait_pidfds(&mut self, deadline: Option<Instant>)
+        -> Result<Selected, Error>
+    {
+        // `wait_select` can't wait forever, the caller repeats on timeout
+        let deadline = deadline
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(86400));
+        // signals taken by a signalfd or by the helper thread aren't seen
+        // by `pselect`, so their descriptor is waited for too
+        let own = self.fd.or_else(|| self.helper.get().map(|h| h.fd()));
+        let mut fds = FdSet::new();
+        if let Some(fd) = own {
+            fds.insert(fd);
+        }
+        for pidfd in &self.pidfds {
+            fds.insert(pidfd.as_raw_fd());
+        }
+        let selected = self.wait_select(Some(&mut fds), None, deadline)?;
+        if let Selected::Ready(_) = selected {
+            if own.is_some_and(|fd| fds.contains(fd)) {
+                if let Some(sig) = self.wait(Instant::now()) {
+                    return Ok(Selected::Signal(sig));
+                }
             }
-            pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&self.oldset), None)
-                .unwrap();
+            let reaped = &mut self.reaped;
+            self.pidfds.retain(|pidfd| {
+                if !fds.contains(pidfd.as_raw_fd()) {
+                    return true;
+                }
+                // `ECHILD` means it's reaped by someone else
+                if let Ok(Some(info)) = pidfd.reap() {
+                    reaped.push_back(ChildEvent {
+                        pid: info.pid,
+                        status: info.status,
+                    });
+                }
+                false
+            });
         }
+        Ok(selected)
     }
-}
 
-impl fmt::Debug for Trap {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Trap")
+    fn event(&mut self, sig: Signal) -> Option<TrapEvent> {
+        if sig == Signal::SIGCHLD && self.reap_children {
+            self.reaped.extend(reap_any());
+            self.reaped.pop_front().map(TrapEvent::Child)
+        } else {
+            Some(TrapEvent::Signal(sig))
+        }
+    }
+
+    /// Returns a `PollFd` to wait for signals with `nix::poll::poll`
+    ///
+    /// Only traps created by `trap_via_signalfd()` or with `Backend::Thread`
+    /// have a descriptor, for others `None` is returned. Put the returned
+    /// value into the array passed to `poll` alongside other descriptors,
+    /// and after `poll` returns pass it to `read_polled()` to get the
+    /// signal.
+    pub fn poll_fd(&self) -> Option<PollFd> {
+        self.fd.or_else(|| self.helper.get().map(|helper| helper.fd()))
+            .map(|fd| PollFd::new(fd, PollFlags::POLLIN))
+    }
+
+    /// Returns a signal if `poll` reported the descriptor as readable
+    ///
+    /// The `pollfd` must be the one returned by `poll_fd()` after `poll`
+    /// has filled in its events. Returns `None` if it isn't readable or if
+    /// the signal was already consumed by someone else. Read errors (which
+    /// are not expected for a `signalfd`) are returned as `None` too.
+    pub fn read_polled(&self, pollfd: &PollFd) -> Option<Signal> {
+        let readable = pollfd.revents()
+            .map(|r| r.contains(PollFlags::POLLIN))
+            .unwrap_or(false);
+        if !readable {
+            return None;
+        }
+        if let Some(helper) = self.helper.get() {
+            return match helper.read() {
+                Ok(Some(info)) => {
+                    from_raw(info.si_signo).map(|sig| self.received(sig))
+                }
+                Ok(None) | Err(_) => None,
+            };
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(fd) = self.fd {
+                return match read_signalfd(fd) {
+                    Ok(Some(info)) => {
+                        from_raw(info.ssi_signo as libc::c_int)
+                            .map(|sig| self.received(sig))
+                    }
+                    Ok(None) | Err(_) => None,
+                };
+            }
+        }
+        None
+    }
+
+    /// Returns a pending signal, for use with readiness-based event loops
+    ///
+    /// Same as `try_wait()` but returns an `io::ErrorKind::WouldBlock`
+    /// error if no signal is pending. For a trap created by
+    /// `trap_via_signalfd()` the signal is read from the descriptor, so
+    /// with edge-triggered readiness (e.g. mio) call it until `WouldBlock`
+    /// on every event.
+    #[cfg(target_os = "linux")]
+    pub fn try_recv(&self) -> io::Result<Signal> {
+        let fd = match self.fd {
+            Some(fd) => fd,
+            None => {
+                return self.try_wait()
+                    .ok_or_else(|| io::ErrorKind::WouldBlock.into());
+            }
+        };
+        loop {
+            let info = read_signalfd(fd).map_err(io_error)?
+                .ok_or(io::ErrorKind::WouldBlock)?;
+            // realtime signals can't be represented, skip them
+            if let Some(sig) = from_raw(info.ssi_signo as libc::c_int) {
+                return Ok(self.received(sig));
+            }
+        }
+    }
+
+    /// Returns the `signalfd` of the trap, if any
+    ///
+    /// Only traps created by `trap_via_signalfd()` have a descriptor. Unlike
+    /// `as_raw_fd()` this doesn't panic for other traps. Signals should be
+    /// read by `read_polled()` or waiting methods of the trap, rather than
+    /// from the descriptor directly.
+    #[cfg(target_os = "linux")]
+    pub fn signal_fd(&self) -> Option<RawFd> {
+        self.fd
+    }
+
+    /// Turn the trap into one which signals can be awaited
+    ///
+    /// If the trap wasn't created by `trap_via_signalfd()` a `signalfd` is
+    /// created for it. See the `async_trap` module for details.
+    #[cfg(target_os = "linux")]
+    pub fn into_async(mut self) -> Result<AsyncTrap, Error> {
+        self.release_helper();
+        if self.fd.is_none() {
+            self.fd = Some(signalfd(SIGNALFD_NEW, &self.sigset,
+                SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+                .map_err(Error::signalfd)?);
+        }
+        AsyncTrap::new(self).map_err(Error::wait)
+    }
+
+    /// Turn the trap into a `futures::Stream` of signals
+    ///
+    /// Same as `into_async()`, but the result implements `Stream`.
+    #[cfg(all(target_os = "linux", feature = "futures"))]
+    pub fn into_stream(self) -> Result<SignalStream, Error> {
+        self.into_async().map(SignalStream::new)
+    }
+
+    fn close_fd(&mut self) {
+        if let Some(fd) = self.fd.take() {
+            let _ = unistd::close(fd);
+        }
+    }
+
+    /// Install empty handlers for the signals without masking them out
+    ///
+    /// This activates signals that are ignored by default (e.g. `SIGCHLD`,
+    /// `SIGWINCH`), but they are delivered asynchronously as usual: the only
+    /// visible effect is that blocking system calls are interrupted with
+    /// `EINTR`. Old handlers are restored on drop, the signal mask is never
+    /// touched.
+    ///
+    /// Since signals aren't blocked, `wait()` and `next()` aren't meaningful
+    /// for such trap: signals delivered while not waiting are consumed by
+    /// the handler and never seen by them.
+    pub fn unmasked(signals: &[Signal]) -> Trap {
+        unsafe { Trap::with_handler(signals, empty_handler) }
+    }
+
+    /// Install a custom signal handler for the signals
+    ///
+    /// This uses `Trap` as a lifecycle manager for the handler: old handlers
+    /// are restored when the trap is dropped. Like for `unmasked()` the signal
+    /// mask is not changed, so signals are delivered to the handler
+    /// asynchronously and `wait()`/`next()` never see them (unless they are
+    /// blocked by other means).
+    ///
+    /// # Safety
+    ///
+    /// The handler may run at any moment on any thread that doesn't block
+    /// the signals, interrupting arbitrary code. It must only call
+    /// async-signal-safe functions (see `signal-safety(7)`): no memory
+    /// allocation, no locking, no `println!`, no panicking. Storing into an
+    /// atomic variable or writing to a pipe is usually all it should do.
+    /// It must also preserve `errno`.
+    pub unsafe fn with_handler(signals: &[Signal],
+        handler: extern "C" fn(libc::c_int))
+        -> Trap
+    {
+        Trap::with_handler_options(signals, handler, HandlerOptions::new())
+    }
+
+    /// Install a custom signal handler with the specified options
+    ///
+    /// Same as `with_handler()` but allows to tune how the handler is
+    /// installed. Old handlers are restored on drop regardless of options.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as for `with_handler()` apply to the handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list contains signals that can't be caught.
+    pub unsafe fn with_handler_options(signals: &[Signal],
+        handler: extern "C" fn(libc::c_int), options: HandlerOptions)
+        -> Trap
+    {
+        check_catchable(signals)
+            .expect("SIGKILL and SIGSTOP can't be caught");
+        let flags = if options.one_shot {
+            SaFlags::SA_RESETHAND
+        } else {
+            SaFlags::empty()
+        };
+        let mut sigset = SigSet::empty();
+        for &sig in signals {
+            sigset.add(sig.into());
+        }
+        let action = SigAction::new(SigHandler::Handler(handler),
+                                    flags, sigset);
+        let empty = empty_handler as extern "C" fn(libc::c_int);
+        let shared = handler as usize == empty as usize;
+        let mut oldsigs = Vec::new();
+        for &sig in signals {
+            let old = if shared {
+                install_shared(sig, &action).unwrap()
+            } else {
+                OldAction::Own(sigaction(sig.into(), &action).unwrap())
+            };
+            oldsigs.push((sig, old));
+        }
+        let mut trap = Trap::new(sigset, None);
+        trap.oldsigs = oldsigs;
+        trap
+    }
+
+    /// Wait until any of signals arrived or timeout occurs. In case of
+    /// timeout returns None, otherwise returns signal number.
+    ///
+    /// Note the argument here is a deadline, not timeout. It's easier to work
+    /// with deadline if you call wait() function in a loop. Consider using
+    /// `wait_deadline()` which makes that explicit at the call site.
+    ///
+    /// The deadline is measured by `CLOCK_MONOTONIC` (the clock of
+    /// `Instant`), which doesn't advance while the system is suspended. The
+    /// remaining timeout is recomputed from the deadline every time the wait
+    /// is restarted (after `EINTR`, or when the process is stopped and
+    /// continued), so interruptions don't extend it. Use `wait_timer()` to
+    /// have the deadline enforced by a kernel timer instead.
+    ///
+    /// On macOS and the BSDs, which have no `sigtimedwait`, a `kqueue` with
+    /// `EVFILT_SIGNAL` events is used to wait for the signals. On other
+    /// systems without `sigtimedwait`, the first call spawns a helper thread
+    /// (see `Backend::Thread`), so it should be made in the thread where the
+    /// trap was created.
+    pub fn wait(&self, deadline: Instant) -> Option<Signal> {
+        match self.wait_result(deadline) {
+            Ok(sig) => sig,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Wait until any of signals arrived or deadline is reached
+    ///
+    /// Same as `wait()` but accepts `Deadline`, so it's impossible to pass
+    /// a timeout by mistake: `trap.wait_deadline(Deadline::after(timeout))`.
+    pub fn wait_deadline(&self, deadline: Deadline) -> Option<Signal> {
+        self.wait(deadline.instant())
+    }
+
+    /// Same as `wait()` but returns full signal information
+    ///
+    /// This tells the sender of the signal (e.g. which process sent
+    /// `SIGTERM`) and the reason, see `SigInfo`. In the coalescing mode
+    /// (see `coalesce()`) `None` is returned until `ack()` is called, the
+    /// same way as for `wait()`.
+    ///
+    /// Errors are returned like in `wait_result()`.
+    #[cfg(target_os = "linux")]
+    pub fn wait_info(&self, deadline: Instant)
+        -> Result<Option<SigInfo>, Error>
+    {
+        self.wait_raw_info(deadline).map(|(_, info)| info)
+            .map_err(Error::wait)
+    }
+
+    /// Same as `wait()` but returns errors instead of panicking
+    ///
+    /// `Ok(None)` means the deadline is reached. An error is returned if the
+    /// trap has no signals (`EINVAL`) or waiting failed for an unexpected
+    /// reason, which `wait()` treats as a bug. Long-running programs may
+    /// prefer to log the error and continue.
+    pub fn wait_result(&self, deadline: Instant)
+        -> Result<Option<Signal>, Error>
+    {
+        self.wait_raw(deadline).map(|detail| detail.signal)
+            .map_err(Error::wait)
+    }
+
+    /// Wait until any of signals arrived or timeout elapses
+    ///
+    /// Same as `wait(Instant::now() + timeout)`. Use `wait()` with a deadline
+    /// when waiting in a loop, so the timeout isn't restarted on every
+    /// iteration.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<Signal> {
+        self.wait(Instant::now() + timeout)
+    }
+
+    /// Returns a pending signal without blocking
+    ///
+    /// Returns `None` if none of the trapped signals is pending. This is the
+    /// same as `wait(Instant::now())`.
+    #[cfg(target_os = "linux")]
+    pub fn try_wait(&self) -> Option<Signal> {
+        self.wait(Instant::now())
+    }
+
+    /// Returns a pending signal without blocking
+    ///
+    /// Returns `None` if none of the trapped signals is pending.
+    #[cfg(not(target_os = "linux"))]
+    pub fn try_wait(&self) -> Option<Signal> {
+        if self.coalesce && self.unacked.load(Ordering::SeqCst) {
+            return None;
+        }
+        let sig = match self.helper.get() {
+            Some(helper) => {
+                let info = helper.read()
+                    .unwrap_or_else(|e| panic!("{}", Error::wait(e)))?;
+                from_raw(info.si_signo)?
+            }
+            None => {
+                let sig = self.first_pending()?;
+                if !consume_pending(sig) {
+                    return None;
+                }
+                sig
+            }
+        };
+        if self.coalesce {
+            self.unacked.store(true, Ordering::SeqCst);
+        }
+        Some(self.received(sig))
+    }
+
+    /// Same as `wait()` but also reports how many times the wait was
+    /// interrupted
+    ///
+    /// Every time a signal handler (for a signal not in the trap) runs while
+    /// waiting, the system call is interrupted with `EINTR` and restarted
+    /// by this library. This allows to find out how often this happens.
+    ///
+    /// It also tells whether the call actually blocked or the deadline was
+    /// already in the past (see `WaitDetail::waited`). Errors are returned
+    /// like in `wait_result()`.
+    pub fn wait_detailed(&self, deadline: Instant)
+        -> Result<WaitDetail, Error>
+    {
+        self.wait_raw(deadline).map_err(Error::wait)
+    }
+
+    /// Same as `wait()` but also returns when the signal was received
+    ///
+    /// The timestamps are taken right after the signal is consumed, so
+    /// they don't include the time the signal spent pending before the
+    /// wait was called.
+    pub fn wait_timed(&self, deadline: Instant) -> Option<TimedSignal> {
+        let signal = self.wait(deadline)?;
+        Some(TimedSignal {
+            signal,
+            instant: Instant::now(),
+            system_time: SystemTime::now(),
+        })
+    }
+
+    /// Wait until `count` signals arrived or deadline is reached
+    ///
+    /// Returns as soon as `count` signals are collected, or on deadline
+    /// with the signals received so far (possibly none). Note that multiple
+    /// instances of the same standard signal arriving while none is consumed
+    /// are coalesced by the kernel into one.
+    pub fn wait_until_count(&self, count: usize, deadline: Instant)
+        -> Vec<Signal>
+    {
+        let mut result = Vec::with_capacity(count);
+        while result.len() < count {
+            match self.wait(deadline) {
+                Some(sig) => result.push(sig),
+                None => break,
+            }
+        }
+        result
+    }
+
+    /// Release the lock, wait for a signal and re-acquire the lock
+    ///
+    /// This is like `Condvar::wait_timeout` but for signals: the lock isn't
+    /// held across a potentially long wait, so other threads can use the
+    /// data meanwhile. Note that they can also modify it, so the state
+    /// should be re-checked after the call. The `guard` must be a guard of
+    /// the `mutex`.
+    ///
+    /// If the mutex was poisoned while waiting, the guard is still returned
+    /// (the poison flag is kept set for other users of the mutex).
+    #[cfg(target_os = "linux")]
+    pub fn wait_releasing<'a, T>(&self, guard: MutexGuard<'a, T>,
+        mutex: &'a Mutex<T>, deadline: Instant)
+        -> (Option<Signal>, MutexGuard<'a, T>)
+    {
+        drop(guard);
+        let sig = self.wait(deadline);
+        let guard = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        (sig, guard)
+    }
+
+    /// Create a notifier that wakes up `wait_or_notified()` from other
+    /// threads
+    ///
+    /// The `signal` is used to interrupt the wait, it must be one of the
+    /// signals of the trap, otherwise `Error::InvalidSignal` is returned.
+    /// It's better to dedicate a signal for that (e.g. `SIGUSR2` or a
+    /// realtime signal), although the signal sent by other means is still
+    /// reported as a signal rather than a notification.
+    #[cfg(target_os = "linux")]
+    pub fn notifier(&self, signal: Signal) -> Result<Notifier, Error> {
+        if !self.sigset.contains(signal.into()) {
+            return Err(Error::InvalidSignal(to_raw(signal)));
+        }
+        Ok(Notifier {
+            thread: self.thread,
+            signal,
+            notified: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Wait until a signal arrives, the notifier is triggered or deadline
+    /// is reached
+    ///
+    /// This is for code mixing signals and `Condvar`-like coordination of
+    /// threads: other threads call `Notifier::notify()` to wake up the
+    /// waiting one. Multiple notifications before the wait are coalesced
+    /// into a single `Wakeup::Notified`.
+    ///
+    /// Should be called in the thread where trap was created, since that's
+    /// the thread the notifier sends the signal to.
+    #[cfg(target_os = "linux")]
+    pub fn wait_or_notified(&self, notifier: &Notifier, deadline: Instant)
+        -> Wakeup
+    {
+        match self.wait(deadline) {
+            Some(sig) if sig == notifier.signal &&
+                notifier.notified.swap(false, Ordering::SeqCst)
+                => Wakeup::Notified,
+            Some(sig) => Wakeup::Signal(sig),
+            None => Wakeup::Timeout,
+        }
+    }
+
+    /// Wait until any of signals arrived or deadline is reached, using a
+    /// `CLOCK_MONOTONIC` timer for the deadline
+    ///
+    /// Instead of passing a relative timeout to the kernel, this arms a
+    /// POSIX timer (`timer_create`) with an absolute deadline that sends
+    /// `timer_signal` to the current thread, and waits without a timeout.
+    /// So the deadline is enforced by the kernel regardless of how the wait
+    /// is interrupted or for how long the process is stopped.
+    ///
+    /// The `timer_signal` must be one of the signals of the trap (otherwise
+    /// `Error::InvalidSignal` is returned) and should be dedicated to this
+    /// purpose: a pending instance of it is discarded when the wait returns
+    /// for other reason. It's reported as a signal if sent by other means
+    /// than a timer.
+    ///
+    /// The signal is waited for the same way as by `wait()` (using the
+    /// `signalfd` or the helper thread if the trap has them), and the
+    /// coalescing mode and the statistics apply. The timer expiring isn't
+    /// counted as a received signal. With the helper thread the timer
+    /// signal is sent to the process rather than to the current thread.
+    #[cfg(target_os = "linux")]
+    pub fn wait_timer(&self, deadline: Instant, timer_signal: Signal)
+        -> Result<Option<Signal>, Error>
+    {
+        if !self.sigset.contains(timer_signal.into()) {
+            return Err(Error::InvalidSignal(to_raw(timer_signal)));
+        }
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if timeout == Duration::from_secs(0) ||
+            self.coalesce && self.unacked.load(Ordering::SeqCst)
+        {
+            return self.wait_result(deadline);
+        }
+        let timer = DeadlineTimer::new(timeout, timer_signal,
+                                       self.helper.get().is_none())
+            .map_err(Error::wait)?;
+        let result = loop {
+            // the timer enforces the deadline, this one is only a fallback
+            let fallback = Instant::now() + Duration::from_secs(86400);
+            let (detail, info) = self.wait_raw_info(fallback)
+                .map_err(Error::wait)?;
+            let sig = match detail.signal {
+                Some(sig) => sig,
+                None => continue,
+            };
+            let expired = sig == timer_signal &&
+                info.is_some_and(|info| info.raw_code() == libc::SI_TIMER);
+            if expired {
+                self.received[to_raw(sig) as usize]
+                    .fetch_sub(1, Ordering::Relaxed);
+                if self.coalesce {
+                    self.unacked.store(false, Ordering::SeqCst);
+                }
+                break None;
+            }
+            break Some(sig);
+        };
+        drop(timer);
+        if result.is_some() {
+            // the timer might have fired right before it was deleted
+            let mut set = SigSet::empty();
+            set.add(timer_signal.into());
+            let zero = timespec { tv_sec: 0, tv_nsec: 0 };
+            unsafe { libc::sigtimedwait(set.as_ref(), null_mut(), &zero) };
+        }
+        Ok(result)
+    }
+
+    /// Wait until descriptors are ready, a signal arrives or deadline is
+    /// reached, using `pselect`
+    ///
+    /// This is for `select`-based code: trapped signals are unblocked
+    /// atomically for the duration of `pselect` only, so a signal arriving
+    /// right before the call isn't missed but interrupts it immediately.
+    /// The descriptor sets are updated like `select` does if any of the
+    /// descriptors are ready.
+    ///
+    /// The signal is consumed by the handler of the trap, which records it
+    /// for this method. Only the signals delivered during `pselect` are
+    /// reported, although the record is process-wide, so a trapped signal
+    /// caught by another thread that has it unblocked at the same time may
+    /// be reported too.
+    ///
+    /// Returns `Error::Wait(EINVAL)` if the handler of the trap isn't
+    /// installed for any of the signals (e.g. the trap is built with
+    /// `unignore(false)`), as unmasking them would run their default
+    /// action.
+    pub fn wait_select(&self, readfds: Option<&mut FdSet>,
+        writefds: Option<&mut FdSet>, deadline: Instant)
+        -> Result<Selected, Error>
+    {
+        let sigs = Signal::iterator()
+            .filter(|&sig| self.sigset.contains(sig.into()));
+        if !sigs.clone().all(has_empty_handler) {
+            return Err(Error::Wait(Errno::EINVAL));
+        }
+        let mut mask = SigSet::thread_get_mask().map_err(Error::sigmask)?;
+        for sig in Signal::iterator() {
+            if self.sigset.contains(sig.into()) {
+                mask.remove(sig.into());
+            }
+        }
+        let mut readfds = readfds;
+        let mut writefds = writefds;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let timeout = TimeSpec::nanoseconds(timeout.as_nanos() as i64);
+            // stale records, e.g. of a handler run before this call
+            clear_delivered(&self.sigset);
+            match pselect(None, readfds.as_deref_mut(),
+                          writefds.as_deref_mut(), None, &timeout, &mask)
+            {
+                Ok(0) => return Ok(Selected::Timeout),
+                Ok(n) => return Ok(Selected::Ready(n as usize)),
+                Err(nix::Error::Sys(Errno::EINTR)) => {
+                    // either our signal or some other one
+                    if let Some(sig) = take_delivered(&self.sigset) {
+                        return Ok(Selected::Signal(self.received(sig)));
+                    }
+                }
+                Err(e) => return Err(Error::wait(e)),
+            }
+        }
+    }
+
+    /// Fallible implementation of `wait()`
+    ///
+    /// Returns `EINVAL` if the trap has no signals to wait for or if the
+    /// kernel rejects the signal set.
+    #[cfg(target_os = "linux")]
+    fn wait_raw(&self, deadline: Instant) -> nix::Result<WaitDetail> {
+        self.wait_raw_info(deadline).map(|(detail, _)| detail)
+    }
+
+    /// Fallible implementation of `wait()` for systems without
+    /// `sigtimedwait`
+    ///
+    /// Uses `kqueue` where available and the helper thread otherwise.
+    #[cfg(not(target_os = "linux"))]
+    fn wait_raw(&self, deadline: Instant) -> nix::Result<WaitDetail> {
+        if self.is_empty() {
+            return Err(nix::Error::Sys(Errno::EINVAL));
+        }
+        let mut detail = WaitDetail {
+            signal: None,
+            interruptions: 0,
+            waited: false,
+        };
+        if self.coalesce && self.unacked.load(Ordering::SeqCst) {
+            // signals are left pending until `ack()`
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            detail.waited = timeout > Duration::from_secs(0);
+            thread::sleep(timeout);
+            return Ok(detail);
+        }
+        #[cfg(any(target_os = "macos", target_os = "ios",
+                  target_os = "freebsd", target_os = "dragonfly",
+                  target_os = "netbsd", target_os = "openbsd"))]
+        {
+            if self.helper.get().is_none() {
+                self.wait_kqueue(&mut detail, deadline)?;
+                return Ok(detail);
+            }
+        }
+        let helper = self.start_helper()?;
+        self.wait_helper(helper, &mut detail, Some(deadline))?;
+        if self.coalesce && detail.signal.is_some() {
+            self.unacked.store(true, Ordering::SeqCst);
+        }
+        Ok(detail)
+    }
+
+    /// Wait for a signal using `kqueue`, filling in the `detail`
+    #[cfg(any(target_os = "macos", target_os = "ios",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd"))]
+    fn wait_kqueue(&self, detail: &mut WaitDetail, deadline: Instant)
+        -> nix::Result<()>
+    {
+        // registered before checking for pending signals, so a signal
+        // arriving in between isn't missed
+        let queue = SignalQueue::new(&self.sigset)?;
+        loop {
+            detail.signal = self.try_wait();
+            if detail.signal.is_some() {
+                return Ok(());
+            }
+            if deadline > Instant::now() {
+                detail.waited = true;
+            }
+            match queue.wait(deadline) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(nix::Error::Sys(Errno::EINTR)) => {
+                    detail.interruptions += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Same as `wait_raw()` but also returns siginfo of the signal
+    #[cfg(target_os = "linux")]
+    fn wait_raw_info(&self, deadline: Instant)
+        -> nix::Result<(WaitDetail, Option<SigInfo>)>
+    {
+        if self.is_empty() {
+            return Err(nix::Error::Sys(Errno::EINVAL));
+        }
+        let mut detail = WaitDetail {
+            signal: None,
+            interruptions: 0,
+            waited: false,
+        };
+        if self.coalesce && self.unacked.load(Ordering::SeqCst) {
+            // signals are left pending until `ack()`
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            detail.waited = timeout > Duration::from_secs(0);
+            thread::sleep(timeout);
+            return Ok((detail, None));
+        }
+        let info = self.wait_signal(&mut detail, deadline)?;
+        if self.coalesce && detail.signal.is_some() {
+            self.unacked.store(true, Ordering::SeqCst);
+        }
+        Ok((detail, info))
+    }
+
+    /// Wait for a signal in the kernel, filling in the `detail`
+    ///
+    /// Returns siginfo of the received signal.
+    #[cfg(target_os = "linux")]
+    fn wait_signal(&self, detail: &mut WaitDetail, deadline: Instant)
+        -> nix::Result<Option<SigInfo>>
+    {
+        use libc::sigtimedwait;
+
+        if let Some(helper) = self.helper.get() {
+            return self.wait_helper(helper, detail, Some(deadline))
+                .map(|info| info.map(|info| SigInfo::from(&info)));
+        }
+        if let Some(fd) = self.fd {
+            loop {
+                if deadline > Instant::now() {
+                    detail.waited = true;
+                }
+                match wait_readable(fd, Some(deadline)) {
+                    Ok(false) => return Ok(None),
+                    Ok(true) => {}
+                    Err(nix::Error::Sys(Errno::EINTR)) => {
+                        detail.interruptions += 1;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                if let Some(info) = read_signalfd(fd)? {
+                    let sig = info.ssi_signo as libc::c_int;
+                    detail.signal =
+                        Some(self.received(Signal::from_c_int(sig)?));
+                    return Ok(Some(SigInfo::from(&info)));
+                }
+            }
+        }
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout > Duration::from_secs(0) {
+                detail.waited = true;
+            }
+            let tm = timespec {
+                tv_sec: timeout.as_secs() as libc::time_t,
+                tv_nsec: timeout.subsec_nanos() as libc::c_long,
+            };
+            let mut info = unsafe { mem::zeroed() };
+            let sig = unsafe { sigtimedwait(self.sigset.as_ref(),
+                                            &mut info, &tm) };
+            if sig > 0 {
+                detail.signal = Some(self.received(Signal::from_c_int(sig)?));
+                return Ok(Some(SigInfo::from(&info)));
+            } else {
+                match Errno::last() {
+                    Errno::EAGAIN => {
+                        return Ok(None);
+                    }
+                    Errno::EINTR => {
+                        detail.interruptions += 1;
+                        continue;
+                    }
+                    err => {
+                        return Err(nix::Error::Sys(err));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for a signal taken by the helper thread, filling in the `detail`
+    ///
+    /// Waits forever if there is no `deadline`.
+    fn wait_helper(&self, helper: &SigwaitThread, detail: &mut WaitDetail,
+        deadline: Option<Instant>)
+        -> nix::Result<Option<libc::siginfo_t>>
+    {
+        loop {
+            if let Some(info) = helper.read()? {
+                let sig = Signal::from_c_int(info.si_signo)?;
+                detail.signal = Some(self.received(sig));
+                return Ok(Some(info));
+            }
+            if deadline.is_none_or(|deadline| deadline > Instant::now()) {
+                detail.waited = true;
+            }
+            match wait_readable(helper.fd(), deadline) {
+                Ok(false) => return Ok(None),
+                Ok(true) => {}
+                Err(nix::Error::Sys(Errno::EINTR)) => {
+                    detail.interruptions += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the helper thread of the trap, spawning it if needed
+    fn start_helper(&self) -> nix::Result<&SigwaitThread> {
+        if let Some(helper) = self.helper.get() {
+            return Ok(helper);
+        }
+        if self.blocked.is_none() {
+            // `sigwait` for signals that aren't blocked is undefined
+            return Err(nix::Error::Sys(Errno::EINVAL));
+        }
+        let helper = SigwaitThread::spawn(&self.sigset)?;
+        Ok(self.helper.get_or_init(|| helper))
+    }
+
+    /// Stop the helper thread, returns the signals it has taken
+    fn stop_helper(&mut self) -> Vec<Signal> {
+        let mut taken = Vec::new();
+        if let Some(mut helper) = self.helper.take() {
+            helper.stop();
+            while let Ok(Some(info)) = helper.read() {
+                taken.extend(from_raw(info.si_signo));
+            }
+        }
+        taken
+    }
+
+    /// Stop the helper thread, making the signals it has taken pending again
+    fn release_helper(&mut self) {
+        for sig in self.stop_helper() {
+            // sent to the process, so that a new helper thread receives it
+            unsafe { libc::kill(libc::getpid(), to_raw(sig)) };
+        }
+    }
+
+    /// Spawn the helper thread again (if any) for the changed signal set
+    fn restart_helper(&mut self) -> nix::Result<()> {
+        if self.helper.get().is_some() {
+            self.release_helper();
+            if !self.is_empty() {
+                self.start_helper()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until any of signals arrived and return full signal information
+    ///
+    /// This is like `try_next()` but uses `sigwaitinfo`, so it also
+    /// returns the sender and the reason of the signal.
+    #[cfg(target_os = "linux")]
+    pub fn next_info(&self) -> Result<SigInfo, Error> {
+        use libc::sigwaitinfo;
+
+        if let Some(helper) = self.helper.get() {
+            let mut detail = WaitDetail {
+                signal: None,
+                interruptions: 0,
+                waited: false,
+            };
+            loop {
+                match self.wait_helper(helper, &mut detail, None) {
+                    Ok(Some(info)) => return Ok(SigInfo::from(&info)),
+                    Ok(None) => continue,
+                    Err(e) => return Err(Error::wait(e)),
+                }
+            }
+        }
+        if let Some(fd) = self.fd {
+            let info = self.read_fd_blocking(fd).map_err(Error::wait)?;
+            return Ok(self.received_info(SigInfo::from(&info)));
+        }
+        loop {
+            let mut info = unsafe { mem::zeroed() };
+            let sig = unsafe { sigwaitinfo(self.sigset.as_ref(), &mut info) };
+            if sig > 0 {
+                return Ok(self.received_info(SigInfo::from(&info)));
+            } else if Errno::last() != Errno::EINTR {
+                return Err(Error::Wait(Errno::last()));
+            }
+        }
+    }
+
+    /// Block until a signal can be read from signalfd
+    #[cfg(target_os = "linux")]
+    fn read_fd_blocking(&self, fd: RawFd)
+        -> nix::Result<libc::signalfd_siginfo>
+    {
+        loop {
+            match wait_readable(fd, None) {
+                Ok(_) => {}
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                Err(e) => return Err(e),
+            }
+            if let Some(info) = read_signalfd(fd)? {
+                return Ok(info);
+            }
+        }
+    }
+
+    /// Returns `true` if there are no signals in the trap
+    pub(crate) fn is_empty(&self) -> bool {
+        is_empty_set(&self.sigset)
+    }
+
+    /// Returns `true` if the trap has blocked any signals
+    ///
+    /// This is `false` when all the signals were already blocked when the
+    /// trap was created (and for traps that don't touch the mask, like
+    /// `unmasked()`). Such signals stay blocked after the trap is dropped,
+    /// and the mask isn't touched on drop at all.
+    pub fn mask_changed(&self) -> bool {
+        self.blocked.as_ref().map(|set| !is_empty_set(set)).unwrap_or(false)
+    }
+
+    /// Returns the set of trapped signals
+    pub fn sigset(&self) -> SigSet {
+        self.sigset
+    }
+
+    /// Discard pending trapped signals when the trap is dropped
+    ///
+    /// By default signals that weren't consumed are delivered when the mask
+    /// is restored on drop (see the type-level docs). With this option they
+    /// are consumed without blocking before restoring handlers and mask, so
+    /// dropping the trap can't suddenly terminate the process.
+    ///
+    /// Signals that arrive after draining but before the mask is restored
+    /// are still delivered.
+    pub fn drain_before_drop(mut self) -> Trap {
+        self.drain = true;
+        self
+    }
+
+    /// Re-raise pending trapped signals after the trap is dropped
+    ///
+    /// By default, signals that weren't consumed are delivered in whatever
+    /// order the kernel chooses as soon as the mask is restored (see the
+    /// type-level docs), which also may happen in any thread for signals
+    /// sent to the whole process. With this option pending signals are
+    /// consumed before restoring handlers and mask, and then raised again
+    /// in the current thread in the order of signal numbers. So they are
+    /// deterministically handled by the restored disposition, e.g. the
+    /// default action of a `SIGTERM` that wasn't acted upon terminates the
+    /// process right in the `drop()`.
+    ///
+    /// Signals that were blocked before the trap was created stay
+    /// pending after re-raising (as they would without this option). If
+    /// `drain_before_drop()` is also set, pending signals are discarded
+    /// instead.
+    pub fn reraise_on_drop(mut self) -> Trap {
+        self.reraise = true;
+        self
+    }
+
+    /// Return at most one signal until acknowledged by `ack()`
+    ///
+    /// This is useful for event loops that want a single signal
+    /// notification per loop iteration however many signals arrive. After
+    /// a signal is returned by `wait()` (or any other method that waits
+    /// with a deadline), subsequent waits don't wake up for signals, they
+    /// just sleep until the deadline. Signals arriving meanwhile are left
+    /// pending and are returned one by one after `ack()` is called.
+    ///
+    /// Note that pending standard signals are coalesced by the kernel:
+    /// if the same signal arrives several times before `ack()`, it's
+    /// returned only once afterwards. Also this doesn't affect the
+    /// iterator and `next_info()`, since they can't be woken otherwise.
+    pub fn coalesce(mut self) -> Trap {
+        self.coalesce = true;
+        self
+    }
+
+    /// Acknowledge the signal returned in the coalescing mode
+    ///
+    /// The next wait returns pending signals again. See `coalesce()`.
+    pub fn ack(&self) {
+        self.unacked.store(false, Ordering::SeqCst);
+    }
+
+    /// Consume all currently pending trapped signals without blocking
+    ///
+    /// Returns the signals consumed, ordered by signal number. This does a
+    /// single `sigpending()` call and then consumes each of the pending
+    /// signals, so a storm of e.g. `SIGCHLD` and `SIGTERM` can be handled at
+    /// once. Signals arriving during the call may or may not be included.
+    ///
+    /// This is not affected by the coalescing mode (see `coalesce()`).
+    pub fn drain(&self) -> Vec<Signal> {
+        if let Some(helper) = self.helper.get() {
+            let mut taken = Vec::new();
+            while let Ok(Some(info)) = helper.read() {
+                taken.extend(from_raw(info.si_signo)
+                    .map(|sig| self.received(sig)));
+            }
+            taken.sort_by_key(|&sig| to_raw(sig));
+            return taken;
+        }
+        let pending = self.pending();
+        Signal::iterator()
+            .filter(|&sig| pending.contains(sig.into()))
+            .filter(|&sig| consume_pending(sig))
+            .map(|sig| self.received(sig))
+            .collect()
+    }
+
+    /// Returns how many times each of the signals was received
+    ///
+    /// Every signal returned by the methods of the trap is counted (and
+    /// by `AsyncTrap`, `SignalStream` or `SignalFd` wrapping it), including
+    /// the ones consumed by `drain()`. Signals discarded on drop and the
+    /// ones merged by the kernel while pending aren't counted.
+    pub fn stats(&self) -> TrapStats {
+        let counts = Signal::iterator()
+            .map(|sig| (sig, self.received[to_raw(sig) as usize]
+                .load(Ordering::Relaxed)))
+            .filter(|&(sig, count)| {
+                count > 0 || self.sigset.contains(sig.into())
+            })
+            .collect();
+        TrapStats { counts }
+    }
+
+    /// Count the signal as received, returns the signal for convenience
+    pub(crate) fn received(&self, sig: Signal) -> Signal {
+        self.received[to_raw(sig) as usize].fetch_add(1, Ordering::Relaxed);
+        sig
+    }
+
+    /// Count the signal of the siginfo as received
+    #[cfg(target_os = "linux")]
+    pub(crate) fn received_info(&self, info: SigInfo) -> SigInfo {
+        if let Some(sig) = info.signal() {
+            self.received(sig);
+        }
+        info
+    }
+
+    /// Start trapping one more signal
+    ///
+    /// The signal is masked out and the empty handler is installed the same
+    /// way as by `trap_checked()`, and it's restored when the trap is
+    /// dropped (or by `remove()`). Does nothing if the signal is already in
+    /// the trap. This allows e.g. trapping `SIGHUP` only after the
+    /// configuration is loaded.
+    ///
+    /// Returns `Error::Sigmask(EINVAL)` if the trap doesn't mask signals out
+    /// (i.e. was created by `unmasked()` or `with_handler()`).
+    pub fn add(&mut self, signal: Signal) -> Result<(), Error> {
+        if self.blocked.is_none() {
+            return Err(Error::Sigmask(Errno::EINVAL));
+        }
+        if self.sigset.contains(signal.into()) {
+            return Ok(());
+        }
+        check_catchable(&[signal])?;
+        let mut one = SigSet::empty();
+        one.add(signal.into());
+        let mut oldset = SigSet::empty();
+        pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&one), Some(&mut oldset))
+            .map_err(Error::sigmask)?;
+        let newly = !oldset.contains(signal.into());
+        let mut sigset = self.sigset;
+        sigset.add(signal.into());
+        let action = SigAction::new(SigHandler::Handler(empty_handler),
+                                    SaFlags::empty(), sigset);
+        let old = match unsafe { install_shared(signal, &action) } {
+            Ok(old) => old,
+            Err(e) => {
+                if newly {
+                    pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(&one), None)
+                        .ok();
+                }
+                return Err(Error::sigaction(signal, e));
+            }
+        };
+        self.oldsigs.push((signal, old));
+        if newly {
+            if let Some(ref mut blocked) = self.blocked {
+                blocked.add(signal.into());
+            }
+        }
+        self.sigset = sigset;
+        self.update_fd()?;
+        self.restart_helper().map_err(Error::wait)?;
+        Ok(())
+    }
+
+    /// Stop trapping the signal
+    ///
+    /// The disposition and the mask of the signal are restored the same way
+    /// as when the trap is dropped. In particular, if the signal is pending
+    /// it's delivered right away with the restored disposition. Does nothing
+    /// if the signal is not in the trap.
+    ///
+    /// The signal is removed even if updating the `signalfd` or restarting
+    /// the helper thread fails, the error is returned afterwards.
+    pub fn remove(&mut self, signal: Signal) -> Result<(), Error> {
+        let idx = match self.oldsigs.iter().position(|&(s, _)| s == signal) {
+            Some(idx) => idx,
+            None => return Ok(()),
+        };
+        let entry = self.oldsigs.remove(idx);
+        self.sigset.remove(signal.into());
+        let result = self.update_fd()
+            .and_then(|()| self.restart_helper().map_err(Error::wait));
+        let mut blocked = None;
+        if let Some(ref mut all_blocked) = self.blocked {
+            if all_blocked.contains(signal.into()) {
+                all_blocked.remove(signal.into());
+                let mut one = SigSet::empty();
+                one.add(signal.into());
+                blocked = Some(one);
+            }
+        }
+        restore(&[entry], blocked.as_ref());
+        result
+    }
+
+    /// Set the mask of the `signalfd` to the signals of the trap
+    #[cfg(target_os = "linux")]
+    fn update_fd(&self) -> Result<(), Error> {
+        if let Some(fd) = self.fd {
+            signalfd(fd, &self.sigset,
+                     SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+                .map_err(Error::signalfd)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn update_fd(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the trapped signals that are currently pending
+    ///
+    /// The signals are not consumed, so they are still returned by
+    /// subsequent waits. Both signals sent to the process and to the
+    /// current thread are included. If `sigpending()` fails (which isn't
+    /// expected), the empty set is returned.
+    pub fn pending(&self) -> SigSet {
+        let mut result = SigSet::empty();
+        let mut pending: libc::sigset_t = unsafe { mem::zeroed() };
+        if unsafe { libc::sigpending(&mut pending) } != 0 {
+            return result;
+        }
+        for sig in Signal::iterator() {
+            if self.sigset.contains(sig.into()) &&
+                unsafe { libc::sigismember(&pending, to_raw(sig)) } == 1
+            {
+                result.add(sig.into());
+            }
+        }
+        result
+    }
+
+    /// Consume all pending trapped signals without blocking
+    ///
+    /// Unlike `drain()`, repeats until no signal is pending. Returns signals
+    /// consumed.
+    fn discard_pending(&self) -> Vec<Signal> {
+        let mut consumed = Vec::new();
+        while self.first_pending().is_some() {
+            consumed.extend(self.drain());
+        }
+        consumed
+    }
+
+    /// Returns any one of the trapped signals that is currently pending
+    fn first_pending(&self) -> Option<Signal> {
+        let pending = self.pending();
+        Signal::iterator().find(|&sig| pending.contains(sig.into()))
+    }
+
+    /// Leave the mask and handlers installed for the process lifetime
+    ///
+    /// The trap is consumed without restoring anything, like with
+    /// `mem::forget()`, except that the `signalfd` (if any) is closed. The
+    /// signals stay blocked in the current thread and in threads spawned
+    /// from it later, so they are pending until waited for by other means
+    /// (e.g. by another trap). Other traps for the same signals don't
+    /// restore the original dispositions on drop either, since the detached
+    /// trap still shares the handler.
+    pub fn detach(mut self) {
+        self.release_helper();
+        self.close_fd();
+        self.oldsigs.clear();
+        // dropping the trap without the mask restores nothing
+        self.blocked = None;
+    }
+
+    /// Disassemble the trap into the signal set and a restore function
+    ///
+    /// The guard is relinquished: nothing is restored until the returned
+    /// function is called. The signal set may be used with any system call
+    /// accepting a mask (`ppoll`, `pselect`, `signalfd`...).
+    ///
+    /// The restore function restores signal handlers and the signal mask
+    /// the same way as dropping the trap does (but never drains pending
+    /// signals, even if `drain_before_drop()` was set). Like `Drop`, the
+    /// mask is restored for the current thread, so it should be called in
+    /// the thread where trap was created.
+    ///
+    /// For a trap created by `trap_via_signalfd()` the descriptor is closed
+    /// right away.
+    pub fn into_parts(mut self) -> (SigSet, impl FnOnce()) {
+        self.release_helper();
+        self.close_fd();
+        let oldsigs = mem::take(&mut self.oldsigs);
+        let blocked = self.blocked.take();
+        // dropping the empty trap restores nothing
+        (self.sigset, move || restore(&oldsigs, blocked.as_ref()))
+    }
+
+    /// Returns an iterator that translates signals into application events
+    ///
+    /// Signals for which `func` returns `None` are skipped. This allows to
+    /// keep signal numbers out of the application code, e.g.:
+    /// `trap.map_signals(|sig| match sig { SIGHUP => Some(Reload), ... })`
+    pub fn map_signals<T, F>(self, func: F) -> MapSignals<F>
+        where F: FnMut(Signal) -> Option<T>
+    {
+        MapSignals {
+            trap: self,
+            func,
+        }
+    }
+
+    /// Returns an iterator over supervisor events, see the `events` module
+    ///
+    /// Usually the trap is created for `TERMINATION_SIGNALS`, `SIGHUP` and
+    /// `SIGCHLD` for this.
+    pub fn events(self) -> Events {
+        Events::new(self)
+    }
+}
+
+/// Yields trapped signals as they arrive
+///
+/// The iteration ends when the trap has no signals left to wait for.
+impl Iterator for Trap {
+    type Item = Signal;
+    fn next(&mut self) -> Option<Signal> {
+        match self.try_next() {
+            Ok(sig) => sig,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+/// Consume the signal which is known to be pending
+///
+/// Returns `false` if it wasn't pending anymore (e.g. consumed by another
+/// thread).
+#[cfg(target_os = "linux")]
+fn consume_pending(sig: Signal) -> bool {
+    let mut one = SigSet::empty();
+    one.add(sig.into());
+    let zero = timespec { tv_sec: 0, tv_nsec: 0 };
+    loop {
+        let res = unsafe {
+            libc::sigtimedwait(one.as_ref(), null_mut(), &zero)
+        };
+        if res > 0 {
+            return true;
+        }
+        if Errno::last() != Errno::EINTR {
+            return false;
+        }
+    }
+}
+
+/// Consume the signal which is known to be pending
+///
+/// There is no non-blocking `sigwait` on this platform, so this blocks if
+/// another thread consumes the signal first.
+#[cfg(not(target_os = "linux"))]
+fn consume_pending(sig: Signal) -> bool {
+    let mut one = SigSet::empty();
+    one.add(sig.into());
+    let mut received: libc::c_int = 0;
+    unsafe { sigwait(one.as_ref(), &mut received) == 0 }
+}
+
+/// A guard restoring the trap when dropped, see `Trap::suspend()`
+pub struct Suspended<'a> {
+    trap: &'a mut Trap,
+    /// Handlers replaced when suspending, to install again
+    handlers: Vec<Option<SigAction>>,
+    /// Whether to spawn the helper thread again
+    helper: bool,
+}
+
+impl<'a> Drop for Suspended<'a> {
+    fn drop(&mut self) {
+        let trap = &mut *self.trap;
+        if let Some(ref blocked) = trap.blocked {
+            if !is_empty_set(blocked) {
+                pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(blocked), None)
+                    .unwrap();
+            }
+        }
+        let empty = SigAction::new(SigHandler::Handler(empty_handler),
+                                   SaFlags::empty(), trap.sigset);
+        unsafe {
+            for (&(sig, ref old), handler) in
+                trap.oldsigs.iter().zip(&self.handlers)
+            {
+                match *old {
+                    OldAction::Own(_) => {
+                        if let Some(ref handler) = *handler {
+                            sigaction(sig.into(), handler).unwrap();
+                        }
+                    }
+                    OldAction::Shared => {
+                        if !retain_shared(sig) {
+                            install_shared(sig,
+                                handler.as_ref().unwrap_or(&empty))
+                                .unwrap();
+                        }
+                    }
+                }
+            }
+        }
+        if self.helper {
+            trap.start_helper().expect("can't restart the helper thread");
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Suspended<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Suspended")
+            .field("trap", &self.trap)
+            .finish()
+    }
+}
+
+/// Run the closure with the signals trapped
+///
+/// The trap is created with `drain_before_drop()`, so signals that arrived
+/// but weren't consumed by the closure are discarded, and then handlers and
+/// mask are restored. This happens whenever the closure returns or panics.
+/// Returns the value returned by the closure, or the error of creating the
+/// trap (see `Trap::trap()`).
+pub fn with_trap<T, F>(signals: &[Signal], func: F) -> Result<T, Error>
+    where F: FnOnce(&mut Trap) -> T
+{
+    let mut trap = Trap::trap(signals)?.drain_before_drop();
+    Ok(func(&mut trap))
+}
+
+/// One-shot POSIX timer signalling the current thread (or the process),
+/// deleted on drop
+#[cfg(target_os = "linux")]
+struct DeadlineTimer(libc::timer_t);
+
+#[cfg(target_os = "linux")]
+impl DeadlineTimer {
+    fn new(timeout: Duration, signal: Signal, this_thread: bool)
+        -> nix::Result<DeadlineTimer>
+    {
+        unsafe {
+            let mut now = timespec { tv_sec: 0, tv_nsec: 0 };
+            if libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) != 0 {
+                return Err(nix::Error::last());
+            }
+            let mut event: libc::sigevent = mem::zeroed();
+            event.sigev_signo = to_raw(signal);
+            if this_thread {
+                event.sigev_notify = libc::SIGEV_THREAD_ID;
+                event.sigev_notify_thread_id =
+                    libc::syscall(libc::SYS_gettid) as libc::c_int;
+            } else {
+                event.sigev_notify = libc::SIGEV_SIGNAL;
+            }
+            let mut timer = mem::zeroed();
+            if libc::timer_create(libc::CLOCK_MONOTONIC,
+                                  &mut event, &mut timer) != 0
+            {
+                return Err(nix::Error::last());
+            }
+            let timer = DeadlineTimer(timer);
+            let nsec = now.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+            let value = libc::itimerspec {
+                it_interval: timespec { tv_sec: 0, tv_nsec: 0 },
+                it_value: timespec {
+                    tv_sec: now.tv_sec + timeout.as_secs() as libc::time_t
+                        + (nsec / 1_000_000_000) as libc::time_t,
+                    tv_nsec: (nsec % 1_000_000_000) as libc::c_long,
+                },
+            };
+            if libc::timer_settime(timer.0, libc::TIMER_ABSTIME,
+                                   &value, null_mut()) != 0
+            {
+                return Err(nix::Error::last());
+            }
+            Ok(timer)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for DeadlineTimer {
+    fn drop(&mut self) {
+        unsafe { libc::timer_delete(self.0) };
+    }
+}
+
+/// Wakes up `Trap::wait_or_notified()` from other threads
+///
+/// Created by `Trap::notifier()`. Notifier may be cloned and sent to other
+/// threads. It must not be used after the thread where trap was created
+/// exits.
+#[derive(Debug, Clone)]
+#[cfg(target_os = "linux")]
+pub struct Notifier {
+    thread: usize,
+    signal: Signal,
+    notified: Arc<AtomicBool>,
+}
+
+/// Reason of the wake up of `Trap::wait_or_notified()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(target_os = "linux")]
+pub enum Wakeup {
+    /// A signal arrived
+    Signal(Signal),
+    /// `Notifier::notify()` was called
+    Notified,
+    /// The deadline has been reached
+    Timeout,
+}
+
+#[cfg(target_os = "linux")]
+impl Notifier {
+    /// Wake up the thread waiting in `Trap::wait_or_notified()`
+    ///
+    /// If the thread isn't waiting right now, the next wait returns
+    /// immediately.
+    pub fn notify(&self) -> nix::Result<()> {
+        self.notified.store(true, Ordering::SeqCst);
+        let res = unsafe {
+            libc::pthread_kill(self.thread as libc::pthread_t,
+                               to_raw(self.signal))
+        };
+        if res != 0 {
+            return Err(nix::Error::Sys(Errno::from_i32(res)));
+        }
+        Ok(())
+    }
+}
+
+/// An iterator adaptor returned by `Trap::map_signals`
+pub struct MapSignals<F> {
+    trap: Trap,
+    func: F,
+}
+
+impl<T, F> Iterator for MapSignals<F>
+    where F: FnMut(Signal) -> Option<T>
+{
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        loop {
+            let sig = self.trap.next()?;
+            if let Some(value) = (self.func)(sig) {
+                return Some(value);
+            }
+        }
+    }
+}
+
+impl<F> fmt::Debug for MapSignals<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapSignals")
+        .field("trap", &self.trap)
+        .finish()
+    }
+}
+
+impl Drop for Trap {
+    fn drop(&mut self) {
+        // stopped first, so that signals arriving from now on stay pending
+        let taken = self.stop_helper();
+        let pending = if (self.drain || self.reraise) &&
+            self.blocked.is_some()
+        {
+            self.discard_pending()
+        } else {
+            Vec::new()
+        };
+        restore(&self.oldsigs, self.blocked.as_ref());
+        self.close_fd();
+        if !self.drain {
+            // signals taken by the helper thread would be pending otherwise
+            for &sig in &taken {
+                unsafe { libc::raise(to_raw(sig)) };
+            }
+        }
+        if self.reraise && !self.drain {
+            for sig in pending {
+                unsafe { libc::raise(to_raw(sig)) };
+            }
+        }
+        if cfg!(debug_assertions) && self.blocked.is_some() &&
+            self.thread != current_thread()
+        {
+            eprintln!("Trap is dropped in a different thread than created, \
+                       the signal mask of the original thread is not \
+                       restored");
+        }
+    }
+}
+
+/// Returns an identifier of the current thread
+///
+/// Unlike `std::thread::current()` this works in thread-local destructors.
+fn current_thread() -> usize {
+    unsafe { libc::pthread_self() as usize }
+}
+
+/// Returns `true` if there are no signals in the set
+fn is_empty_set(sigset: &SigSet) -> bool {
+    !Signal::iterator().any(|sig| sigset.contains(sig.into()))
+}
+
+/// Returns signals of `sigset` that are not in `oldset`
+fn newly_blocked(sigset: &SigSet, oldset: &SigSet) -> SigSet {
+    let mut blocked = SigSet::empty();
+    for sig in Signal::iterator() {
+        if sigset.contains(sig.into()) && !oldset.contains(sig.into()) {
+            blocked.add(sig.into());
+        }
+    }
+    blocked
+}
+
+/// Disposition to restore when the trap is dropped
+enum OldAction {
+    /// The disposition replaced by this trap
+    Own(SigAction),
+    /// The empty handler shared by traps, see `install_shared()`
+    Shared,
+}
+
+/// Original dispositions of the signals that have the empty handler set by
+/// traps, with the number of traps sharing the handler
+static SHARED: Mutex<Vec<(Signal, SigAction, usize)>> =
+    Mutex::new(Vec::new());
+
+/// Install the empty handler, remembering the true original disposition
+///
+/// If another trap already has the empty handler installed for the signal,
+/// the disposition saved by that trap is kept, so the original one is
+/// restored when the last of the traps is dropped, whatever the order of
+/// the drops is.
+unsafe fn install_shared(sig: Signal, action: &SigAction)
+    -> nix::Result<OldAction>
+{
+    let mut shared = SHARED.lock().unwrap_or_else(PoisonError::into_inner);
+    let old = sigaction(sig.into(), action)?;
+    match shared.iter_mut().find(|entry| entry.0 == sig) {
+        Some(entry) => entry.2 += 1,
+        None => shared.push((sig, old, 1)),
+    }
+    Ok(OldAction::Shared)
+}
+
+/// Restore the original disposition if no other trap shares the handler
+///
+/// Returns the replaced handler if the disposition was restored.
+unsafe fn release_shared(sig: Signal) -> Option<SigAction> {
+    let mut shared = SHARED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(idx) = shared.iter().position(|entry| entry.0 == sig) {
+        shared[idx].2 -= 1;
+        if shared[idx].2 == 0 {
+            let (_, old, _) = shared.swap_remove(idx);
+            return Some(sigaction(sig.into(), &old).unwrap());
+        }
+    }
+    None
+}
+
+/// Join the traps sharing the handler, if there are any
+///
+/// Returns `false` if no other trap has the handler installed.
+fn retain_shared(sig: Signal) -> bool {
+    let mut shared = SHARED.lock().unwrap_or_else(PoisonError::into_inner);
+    match shared.iter_mut().find(|entry| entry.0 == sig) {
+        Some(entry) => {
+            entry.2 += 1;
+            true
+        }
+        None => false,
+    }
+}
+
+fn restore(oldsigs: &[(Signal, OldAction)], blocked: Option<&SigSet>) {
+    unsafe {
+        for &(sig, ref old) in oldsigs.iter() {
+            match *old {
+                OldAction::Own(ref sigact) => {
+                    sigaction(sig.into(), sigact).unwrap();
+                }
+                OldAction::Shared => {
+                    release_shared(sig);
+                }
+            }
+        }
+        match blocked {
+            Some(blocked) if !is_empty_set(blocked) => {
+                pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(blocked), None)
+                    .unwrap();
+            }
+            // nothing was blocked by the trap
+            _ => {}
+        }
+    }
+}
+
+/// Returns the `signalfd` to register the trap in a poll/epoll reactor
+///
+/// When the descriptor is readable, call `read_polled()`, `wait()` or any
+/// other waiting method to get the signal.
+///
+/// # Panics
+///
+/// Panics if the trap wasn't created by `trap_via_signalfd()`, use
+/// `signal_fd()` to check.
+#[cfg(target_os = "linux")]
+impl AsRawFd for Trap {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.expect("trap is not created by trap_via_signalfd()")
+    }
+}
+
+/// Registers the `signalfd` of the trap in a `mio::Poll`
+///
+/// Registering a trap that wasn't created by `trap_via_signalfd()` fails
+/// with `io::ErrorKind::InvalidInput`. Drain it with `try_recv()`.
+#[cfg(all(target_os = "linux", feature = "mio"))]
+impl event::Source for Trap {
+    fn register(&mut self, registry: &Registry, token: Token,
+        interests: Interest)
+        -> io::Result<()>
+    {
+        SourceFd(&self.mio_fd()?).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token,
+        interests: Interest)
+        -> io::Result<()>
+    {
+        SourceFd(&self.mio_fd()?).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.mio_fd()?).deregister(registry)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "mio"))]
+impl Trap {
+    fn mio_fd(&self) -> io::Result<RawFd> {
+        self.fd.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+            "trap is not created by trap_via_signalfd()"))
+    }
+}
+
+impl fmt::Debug for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Trap")
+        .finish()
+    }
+}
+
+/// A trap that may be cheaply activated and deactivated many times
+///
+/// Unlike `Trap` which changes signal handlers and the signal mask on every
+/// construction and drop, `ReusableTrap` captures original handlers and
+/// installs empty handlers once, and then `arm()` and `disarm()` only change
+/// the signal mask. Original handlers are restored on drop.
+///
+/// Note: while the trap is disarmed the signals are delivered to the empty
+/// handler, i.e. they are effectively ignored (except that they interrupt
+/// blocking system calls). In particular `SIGTERM` and `SIGINT` don't
+/// terminate the process while disarmed.
+pub struct ReusableTrap {
+    trap: Option<Trap>,
+    blocked: Option<SigSet>,
+}
+
+impl ReusableTrap {
+    /// Install handlers for the specified signals, the trap starts disarmed
+    pub fn new(signals: &[Signal]) -> ReusableTrap {
+        ReusableTrap {
+            trap: Some(Trap::unmasked(signals)),
+            blocked: None,
+        }
+    }
+
+    fn inner(&self) -> &Trap {
+        self.trap.as_ref().expect("trap is only taken on drop")
+    }
+
+    /// Mask out the signals, so they can be waited for
+    ///
+    /// Does nothing if the trap is already armed.
+    pub fn arm(&mut self) {
+        if self.blocked.is_some() {
+            return;
+        }
+        let sigset = self.inner().sigset;
+        let mut oldset = SigSet::empty();
+        pthread_sigmask(SigmaskHow::SIG_BLOCK,
+                        Some(&sigset), Some(&mut oldset))
+            .unwrap();
+        // Only remember signals that weren't blocked before, so disarming
+        // doesn't unblock signals blocked by somebody else
+        self.blocked = Some(newly_blocked(&sigset, &oldset));
+    }
+
+    /// Unmask the signals, signals received while disarmed are ignored
+    ///
+    /// Signals that are pending when the trap is disarmed are delivered to
+    /// the empty handler, i.e. discarded. Does nothing if the trap is not
+    /// armed.
+    pub fn disarm(&mut self) {
+        if let Some(blocked) = self.blocked.take() {
+            pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(&blocked), None)
+                .unwrap();
+        }
+    }
+
+    /// Returns `true` if the trap is armed
+    pub fn is_armed(&self) -> bool {
+        self.blocked.is_some()
+    }
+
+    /// Wait until any of signals arrived or deadline is reached
+    ///
+    /// See `Trap::wait` for more info. The trap should be armed, otherwise
+    /// signals arriving outside of this call are lost.
+    pub fn wait(&self, deadline: Instant) -> Option<Signal> {
+        self.inner().wait(deadline)
+    }
+}
+
+impl Iterator for ReusableTrap {
+    type Item = Signal;
+    fn next(&mut self) -> Option<Signal> {
+        self.trap.as_mut().expect("trap is only taken on drop").next()
+    }
+}
+
+impl Drop for ReusableTrap {
+    fn drop(&mut self) {
+        // restore handlers before unmasking, like `Trap` does
+        self.trap.take();
+        self.disarm();
+    }
+}
+
+impl fmt::Debug for ReusableTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReusableTrap")
+        .field("armed", &self.is_armed())
         .finish()
     }
 }
@@ -0,0 +1,64 @@
+//! Parsing signals from the command line with `clap`
+//!
+//! `SignalValueParser` accepts the same values as `Signal::from_str`: the
+//! full name (`SIGTERM`), the short one (`TERM`, `term`) or the number. It's
+//! also the default parser for `Signal`, so an argument can be declared with
+//! `value_parser!(Signal)` and read with `get_one::<Signal>()`. The names
+//! are reported as possible values, for help and shell completion.
+//!
+//! Only available with the `clap` feature.
+
+use std::ffi::OsStr;
+
+use clap::builder::{PossibleValue, TypedValueParser, ValueParserFactory};
+use clap::error::{Error, ErrorKind};
+use clap::{Arg, Command};
+
+use Signal;
+
+
+/// The `clap` value parser for `Signal`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalValueParser;
+
+impl SignalValueParser {
+    /// Create the parser
+    pub fn new() -> SignalValueParser {
+        SignalValueParser
+    }
+}
+
+impl TypedValueParser for SignalValueParser {
+    type Value = Signal;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr)
+        -> Result<Signal, Error>
+    {
+        let value = value.to_string_lossy();
+        value.parse().map_err(|_| {
+            let arg = arg.map_or_else(|| "...".into(), |arg| arg.to_string());
+            Error::raw(ErrorKind::InvalidValue, format!(
+                "invalid value '{}' for '{}': expected a signal name \
+                 (e.g. SIGTERM or TERM) or a signal number\n",
+                value, arg))
+            .with_cmd(cmd)
+        })
+    }
+
+    fn possible_values(&self)
+        -> Option<Box<dyn Iterator<Item=PossibleValue> + '_>>
+    {
+        Some(Box::new(Signal::iterator().map(|sig| {
+            PossibleValue::new(sig.as_str())
+                .alias(&sig.as_str()[3..])
+                .help(sig.description())
+        })))
+    }
+}
+
+impl ValueParserFactory for Signal {
+    type Parser = SignalValueParser;
+    fn value_parser() -> SignalValueParser {
+        SignalValueParser
+    }
+}
@@ -0,0 +1,131 @@
+//! Tests of `Trap` changing the process-wide signal state
+//!
+//! Every case runs in a forked child, so the tests don't interfere with
+//! each other. The libtest harness is disabled (see `Cargo.toml`): it runs
+//! tests in threads, and a child forked from a multithreaded process may
+//! only make async-signal-safe calls. Here the cases run one by one in the
+//! main thread, so the child is free to allocate and panic.
+
+#[cfg(unix)] extern crate libc;
+#[cfg(unix)] extern crate nix;
+#[cfg(unix)] extern crate signal;
+
+#[cfg(unix)]
+mod cases {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::{Duration, Instant};
+
+    use libc;
+    use nix::sys::signal::{kill, raise, SigSet};
+    use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+    use nix::unistd::{fork, ForkResult};
+
+    use signal::Signal::{self, SIGHUP, SIGTSTP, SIGTTIN, SIGTTOU};
+    use signal::Signal::{SIGUSR1, SIGUSR2};
+    use signal::trap::Trap;
+
+    pub const CASES: &[(&str, fn())] = &[
+        ("job_control_signals_dont_stop", job_control_signals_dont_stop),
+        ("drained_signals_dont_fire_after_drop",
+            drained_signals_dont_fire_after_drop),
+        ("drop_keeps_signals_blocked_before_trap",
+            drop_keeps_signals_blocked_before_trap),
+        ("removing_last_signal_ends_iteration",
+            removing_last_signal_ends_iteration),
+    ];
+
+    /// Run `func` in a child process with an empty signal mask
+    ///
+    /// Returns the status of the child, which exits with zero if `func`
+    /// didn't panic.
+    pub fn in_child(func: fn()) -> WaitStatus {
+        match fork().unwrap() {
+            ForkResult::Child => {
+                SigSet::empty().thread_set_mask().unwrap();
+                let code = match panic::catch_unwind(AssertUnwindSafe(func)) {
+                    Ok(()) => 0,
+                    Err(_) => 1,
+                };
+                unsafe { libc::_exit(code) };
+            }
+            ForkResult::Parent { child } => {
+                let status = waitpid(child, Some(WaitPidFlag::WUNTRACED))
+                    .unwrap();
+                if let WaitStatus::Stopped(..) = status {
+                    kill(child, Some(Signal::SIGKILL.into())).ok();
+                    waitpid(child, None).ok();
+                }
+                status
+            }
+        }
+    }
+
+    fn soon() -> Instant {
+        Instant::now() + Duration::from_secs(1)
+    }
+
+    fn job_control_signals_dont_stop() {
+        let signals = [SIGTSTP, SIGTTIN, SIGTTOU];
+        let trap = Trap::trap_checked(&signals).unwrap();
+        for &sig in &signals {
+            raise(sig.into()).unwrap();
+            assert_eq!(trap.wait(soon()), Some(sig));
+        }
+    }
+
+    fn drained_signals_dont_fire_after_drop() {
+        let trap = Trap::trap(&[SIGUSR1]).unwrap().drain_before_drop();
+        raise(SIGUSR1.into()).unwrap();
+        assert!(trap.pending().contains(SIGUSR1.into()));
+        // the default action would kill the child right here
+        drop(trap);
+        let mask = SigSet::thread_get_mask().unwrap();
+        assert!(!mask.contains(SIGUSR1.into()));
+    }
+
+    fn drop_keeps_signals_blocked_before_trap() {
+        let mut before = SigSet::empty();
+        before.add(SIGUSR2.into());
+        before.add(SIGHUP.into());
+        before.thread_block().unwrap();
+        drop(Trap::trap(&[SIGUSR1, SIGHUP]).unwrap());
+        let mask = SigSet::thread_get_mask().unwrap();
+        assert!(mask.contains(SIGUSR2.into()));
+        assert!(mask.contains(SIGHUP.into()));
+        assert!(!mask.contains(SIGUSR1.into()));
+    }
+
+    fn removing_last_signal_ends_iteration() {
+        let mut trap = Trap::trap(&[SIGUSR1, SIGUSR2]).unwrap();
+        raise(SIGUSR1.into()).unwrap();
+        assert_eq!(trap.next(), Some(SIGUSR1));
+        trap.remove(SIGUSR1).unwrap();
+        raise(SIGUSR2.into()).unwrap();
+        assert_eq!(trap.next(), Some(SIGUSR2));
+        trap.remove(SIGUSR2).unwrap();
+        assert!(matches!(trap.try_next(), Ok(None)));
+        assert_eq!(trap.next(), None);
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    use nix::sys::wait::WaitStatus;
+
+    let mut failed = 0;
+    for &(name, func) in cases::CASES {
+        let status = cases::in_child(func);
+        if let WaitStatus::Exited(_, 0) = status {
+            println!("test {} ... ok", name);
+        } else {
+            println!("test {} ... FAILED ({:?})", name, status);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {}